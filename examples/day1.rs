@@ -1,62 +1,58 @@
 #[allow(unused)]
 use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
+use adventofcode2023::tokens::TokenScanner;
 
 type Data = String;
 fn parse_input(input: &str) -> Data {
     input.into()
 }
 
+const DIGITS: [(&str, usize); 10] = [
+    ("0", 0),
+    ("1", 1),
+    ("2", 2),
+    ("3", 3),
+    ("4", 4),
+    ("5", 5),
+    ("6", 6),
+    ("7", 7),
+    ("8", 8),
+    ("9", 9),
+];
+
+const WORDS: [(&str, usize); 9] = [
+    ("one", 1),
+    ("two", 2),
+    ("three", 3),
+    ("four", 4),
+    ("five", 5),
+    ("six", 6),
+    ("seven", 7),
+    ("eight", 8),
+    ("nine", 9),
+];
+
+// The first and last values on the line, as a two-digit number.
+fn calibration(scanner: &TokenScanner<usize>, line: &str) -> usize {
+    let first = scanner.first(line).expect("No digits on line");
+    let last = scanner.last(line).unwrap();
+    first.value * 10 + last.value
+}
+
+fn sum_calibrations(data: &Data, scanner: &TokenScanner<usize>) -> usize {
+    data.lines()
+        .map(|line| calibration(scanner, line))
+        .sum()
+}
+
 timeit!{
 fn part1(data: &Data) -> usize {
-    let mut sum = 0usize;
-    for line in data.lines() {
-        let digits = line
-            .chars()
-            .filter(|c| *c >= '0' && *c <= '9')
-            .map(|c| (c as usize) - '0' as usize)
-            .collect::<Vec<_>>();
-        sum += digits[0] * 10 + digits.last().unwrap();
-    }
-    sum
+    sum_calibrations(data, &TokenScanner::new(DIGITS))
 }}
 
 timeit!{
 fn part2(data: &Data) -> usize {
-    let mut sum = 0usize;
-    let digits = [
-        ("0", 0),
-        ("1", 1),
-        ("2", 2),
-        ("3", 3),
-        ("4", 4),
-        ("5", 5),
-        ("6", 6),
-        ("7", 7),
-        ("8", 8),
-        ("9", 9),
-        ("one", 1),
-        ("two", 2),
-        ("three", 3),
-        ("four", 4),
-        ("five", 5),
-        ("six", 6),
-        ("seven", 7),
-        ("eight", 8),
-        ("nine", 9),
-    ];
-    for line in data.lines() {
-        let mut vals = Vec::new();
-        for i in 0..line.len() {
-            for (s, v) in &digits {
-                if line[i..].starts_with(s) {
-                    vals.push(v);
-                    break;
-                }
-            }
-        }
-        sum += dbg!(vals[0] * 10 + *vals.last().unwrap());
-    }
-    sum
+    sum_calibrations(data, &TokenScanner::new(DIGITS.into_iter().chain(WORDS)))
 }}
 
 #[test]
@@ -78,6 +74,25 @@ zoneight234
 
     assert_eq!(part1(&data1), 142);
     assert_eq!(part2(&data2), 281);
+
+    let scanner = TokenScanner::new(DIGITS.into_iter().chain(WORDS));
+    let values = |line| scanner.all(line).iter().map(|t| *t.value).collect::<Vec<_>>();
+    assert_eq!(values("xtwone3four"), vec![2, 1, 3, 4]);
+    assert_eq!(values("eighthree"), vec![8, 3]);
+    assert_eq!(values("oneight"), vec![1, 8]);
+    assert_eq!(values("abc"), vec![]);
+    let last = scanner.last("zoneight").unwrap();
+    assert_eq!((last.start, last.end, *last.value), (3, 8, 8));
+
+    // Other languages, and words which are suffixes of others
+    let german = TokenScanner::new([("eins", 1), ("zwei", 2), ("drei", 3), ("sieben", 7),
+                                    ("ein", 1), ("neun", 9), ("un", 100)]);
+    let found = german.all("einsiebenzweineun")
+        .iter()
+        .map(|t| (t.start, *t.value))
+        .collect::<Vec<_>>();
+    assert_eq!(found, vec![(0, 1), (0, 1), (3, 7), (9, 2), (11, 1), (13, 9), (15, 100)]);
+    assert_eq!(calibration(&TokenScanner::new([("sieben", 7), ("zwei", 2)]), "siebenzwei"), 72);
 }
 
 fn main() -> std::io::Result<()>{
@@ -1,171 +1,21 @@
-use std::collections::HashSet;
-
 #[allow(unused)]
 use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
+use adventofcode2023::pipes::Maze;
+use adventofcode2023::viz::Frame;
 
-struct Data {
-    field: Vec<Vec<u8>>,
-    start_pos: (usize, usize),
-}
-
-impl Data {
-    pub fn is_connected(&self, (x1, y1): (usize, usize), (x2, y2): (usize, usize)) -> bool {
-        if x1 == x2 {
-            // Vertical connection
-            let n1 = y1.min(y2);
-            let n2 = y1.max(y2);
-            // Check if the top one (lower Y coord) connects down (or might)
-            if ![b'|', b'7', b'F', b'S'].contains(&self.field[n1][x1]) {
-                return false;
-            }
-            if ![b'|', b'L', b'J', b'S'].contains(&self.field[n2][x1]) {
-                return false;
-            }
-            true
-        } else if y1 == y2 {
-            // Horizontal connection
-            let n1 = x1.min(x2);
-            let n2 = x1.max(x2);
-            // Check if the left one connects right (or might)
-            if ![b'-', b'L', b'F', b'S'].contains(&self.field[y1][n1]) {
-                return false;
-            }
-            if ![b'-', b'J', b'7', b'S'].contains(&self.field[y1][n2]) {
-                return false;
-            }
-            true
-        } else {
-            // Not vert/horiz connected
-            panic!()
-        }
-    }
-    pub fn adjacent(&self, (x, y): (usize, usize)) -> Vec<(usize, usize)> {
-        let mut result = Vec::new();
-        if x > 0 && self.is_connected((x, y), (x-1, y)) {
-            result.push((x-1, y));
-        }
-        if x+1 < self.field[y].len() && self.is_connected((x, y), (x+1, y)) {
-            result.push((x+1, y));
-        }
-        if y > 0 && self.is_connected((x, y), (x, y-1)) {
-            result.push((x, y-1));
-        }
-        if y+1 < self.field.len() && self.is_connected((x, y), (x, y+1)) {
-            result.push((x, y+1));
-        }
-        result
-    }
-    pub fn connected(&self, (x, y): (usize, usize)) -> Vec<(usize, usize)> {
-        let adjac = self.adjacent((x, y));
-        adjac.into_iter()
-             .filter(|&pos| self.is_connected((x, y), pos))
-             .collect()
-    }
-}
-
+type Data = Maze;
 fn parse_input(input: &str) -> Data {
-    let field = input.lines()
-        .map(|l| Vec::from(l.as_bytes()))
-        .collect::<Vec<Vec<u8>>>();
-
-    let mut start_pos = None;
-    'top: for (y, row) in field.iter().enumerate() {
-        for (x, &b) in row.iter().enumerate() {
-            if b == b'S' {
-                start_pos = Some((x, y));
-                break 'top;
-            }
-        }
-    };
-    let start_pos = start_pos.unwrap();
-
-    Data {
-        field, start_pos
-    }
+    Maze::parse(input)
 }
 
 timeit!{
 fn part1(data: &Data) -> usize {
-    let mut last_pos = data.start_pos;
-    let mut pos = data.start_pos;
-    let mut count = 0;
-
-    loop {
-        let connections = data.connected(pos);
-        assert_eq!(connections.len(), 2);
-        let new_pos = if connections[0] == last_pos {
-            connections[1]
-        } else {
-            // The first time around, last_pos will be wrong, so we'll pick
-            // a random direction
-            connections[0]
-        };
-        last_pos = pos;
-        pos = new_pos;
-        count += 1;
-        if pos == data.start_pos {
-            return count / 2;
-        }
-    }
+    data.path().len() / 2
 }}
 
 timeit!{
 fn part2(data: &Data) -> usize {
-    let mut last_pos = data.start_pos;
-    let mut pos = data.start_pos;
-
-    let mut in_pipe = HashSet::new();
-
-    loop {
-        let connections = data.connected(pos);
-        assert_eq!(connections.len(), 2);
-        let new_pos = if connections[0] == last_pos {
-            connections[1]
-        } else {
-            // The first time around, last_pos will be wrong, so we'll pick
-            // a random direction
-            connections[0]
-        };
-        last_pos = pos;
-        pos = new_pos;
-        in_pipe.insert(pos);
-        if pos == data.start_pos {
-            break;
-        }
-    }
-
-    let mut count = 0;
-    //let mut outmap = Vec::new();
-    for (y, row) in data.field.iter().enumerate() {
-        //let mut out_row = String::new();
-
-        let mut inside = false;
-
-        for (x, _) in row.iter().enumerate() {
-            if in_pipe.contains(&(x, y)) {
-                // Think of the trace being just below the centre of the square,
-                // so that we don't have to worry about horizontal parts, and just
-                // consider a crossing if this cell connects to the one to the
-                // south.
-                if data.is_connected((x,y), (x, y+1)) {
-                    inside = !inside;
-                }
-                //out_row.push(data.field[y][x] as char);
-            } else if inside {
-                count += 1;
-                //out_row.push('I');
-            } else {
-                //out_row.push('O');
-            }
-        }
-        //outmap.push(out_row);
-    }
-    /*
-    for s in outmap {
-        println!("{}", s);
-    }
-    */
-    count
+    data.enclosed_count()
 }}
 
 #[test]
@@ -178,6 +28,8 @@ LJ..."#;
     let data = parse_input(&tests);
 
     assert_eq!(part1(&data), 8);
+    assert_eq!(data.start_pipe(), b'F');
+    assert_eq!(data.path()[..3], [(0, 2), (1, 2), (1, 1)]);
 
     let test2_1 = r#"...........
 .S-------7.
@@ -203,6 +55,22 @@ L--J.L7...LJS7F-7L7.
     let data2_2 = parse_input(&test2_2);
     assert_eq!(part2(&data2_1), 4);
     assert_eq!(part2(&data2_2), 8);
+    assert_eq!(data2_1.enclosed_tiles(), vec![(2, 6), (3, 6), (7, 6), (8, 6)]);
+    assert_eq!(data2_2.enclosed_tiles().len(), 8);
+    assert_eq!(data2_2.start_pipe(), b'F');
+
+    // Stray pipes pointing at the start don't confuse it.
+    let stray = parse_input(".|...\n-S-7.\n.|.|.\n.L-J.\n.....");
+    assert_eq!(stray.start_pipe(), b'F');
+    assert_eq!(part1(&stray), 4);
+    assert_eq!(part2(&stray), 1);
+    assert_eq!(Frame::capture(&stray).to_text(),
+               "     \n ┌─┐ \n │I│ \n └─┘ \n     \n");
+
+    // Unknown tiles are treated as ground.
+    let odd = parse_input("x|?..\n-S-7.\n.|O|.\n.L-J.\n..*..");
+    assert_eq!(part1(&odd), 4);
+    assert_eq!(part2(&odd), 1);
 }
 
 fn main() -> std::io::Result<()>{
@@ -216,5 +84,9 @@ fn main() -> std::io::Result<()>{
     // Part 2
     println!("{}", part2(&data));
 
+    if std::env::args().any(|arg| arg == "--render") {
+        print!("{}", Frame::capture(&data).to_ansi());
+    }
+
     Ok(())
 }
@@ -1,97 +1,94 @@
-use std::collections::HashSet;
-
 #[allow(unused)]
 use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
 
-type Data = HashSet<(usize, usize)>;
+// Galaxies in reading order
+type Data = Vec<(usize, usize)>;
 fn parse_input(input: &str) -> Data {
-    let mut result = HashSet::new();
+    let mut result = Vec::new();
     for (y, line) in input.lines().enumerate() {
         for (x, b) in line.chars().enumerate() {
             if b == '#' {
-                result.insert((x, y));
+                result.push((x, y));
             }
         }
     }
     result
 }
 
-fn dist((x1, y1): (usize, usize), (x2, y2): (usize, usize)) -> usize {
-    let mut d = 0;
-    if x1 > x2 {
-        d += x1 - x2;
-    } else {
-        d += x2 - x1;
-    }
-    if y1 > y2 {
-        d += y1 - y2;
-    } else {
-        d += y2 - y1;
+// Where each coordinate ends up once every empty line before it has
+// grown to `factor` lines.
+fn expand_axis(coords: &[usize], factor: u128) -> Vec<u128> {
+    let mut occupied = coords.to_vec();
+    occupied.sort();
+    occupied.dedup();
+    coords.iter()
+        .map(|&c| {
+            // The number of occupied lines before this one
+            let rank = occupied.binary_search(&c).unwrap();
+            let empty = (c - rank) as u128;
+            c as u128 + empty * (factor - 1)
+        })
+        .collect()
+}
+
+// The sum of |a - b| over all pairs.
+fn total_distance(values: &[u128]) -> u128 {
+    let mut values = values.to_vec();
+    values.sort();
+    let mut before = 0;
+    let mut total = 0;
+    for (i, &v) in values.iter().enumerate() {
+        total += v * i as u128 - before;
+        before += v;
     }
-    d
+    total
 }
 
-fn with_expansion(data: &Data, exp: usize) -> usize {
-    let mut row_map = Vec::new();
-    let mut col_map = Vec::new();
-
-    let x_values =
-        data.iter()
-            .map(|p| p.0)
-            .collect::<HashSet<usize>>();
-    let y_values =
-        data.iter()
-            .map(|p| p.1)
-            .collect::<HashSet<usize>>();
-    let x_max = x_values.iter().cloned().max().unwrap();
-    let y_max = y_values.iter().cloned().max().unwrap();
-
-    {
-        let mut x = 0usize;
-        for x_in in 0..=x_max {
-            col_map.push(x);
-            if x_values.contains(&x_in) {
-                x += 1;
-            } else {
-                // Expansion
-                x += exp;
-            }
+/// The galaxies after expansion.
+struct Universe {
+    xs: Vec<u128>,
+    ys: Vec<u128>,
+}
+
+impl Universe {
+    pub fn new(data: &Data, factor: u128) -> Universe {
+        let xs = data.iter().map(|g| g.0).collect::<Vec<_>>();
+        let ys = data.iter().map(|g| g.1).collect::<Vec<_>>();
+        Universe {
+            xs: expand_axis(&xs, factor),
+            ys: expand_axis(&ys, factor),
         }
     }
-    {
-        let mut y = 0usize;
-        for y_in in 0..=y_max {
-            row_map.push(y);
-            if y_values.contains(&y_in) {
-                y += 1;
-            } else {
-                // Expansion
-                y += exp;
-            }
-        }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.xs.len()
     }
-    let mut sum = 0;
-    for g1 in data.iter() {
-        for g2 in data.iter() {
-            if g1.0 > g2.0 || (g1.0 == g2.0 && g1.1 > g2.1) {
-                let x1 = col_map[g1.0];
-                let y1 = row_map[g1.1];
-                let x2 = col_map[g2.0];
-                let y2 = row_map[g2.1];
-                sum += dist((x1, y1), (x2, y2));
-            }
-        }
+
+    /// The distance between two galaxies, numbered from 0 in reading
+    /// order.
+    #[allow(unused)]
+    pub fn distance(&self, a: usize, b: usize) -> u128 {
+        self.xs[a].abs_diff(self.xs[b]) + self.ys[a].abs_diff(self.ys[b])
+    }
+
+    /// The sum of the distances between every pair of galaxies.
+    pub fn total_distance(&self) -> u128 {
+        total_distance(&self.xs) + total_distance(&self.ys)
     }
-    sum
+}
+
+fn with_expansion(data: &Data, factor: u128) -> u128 {
+    Universe::new(data, factor).total_distance()
 }
 
 timeit!{
-fn part1(data: &Data) -> usize {
+fn part1(data: &Data) -> u128 {
     with_expansion(data, 2)
 }}
 
 timeit!{
-fn part2(data: &Data) -> usize {
+fn part2(data: &Data) -> u128 {
     with_expansion(data, 1000000)
 }}
 
@@ -112,6 +109,22 @@ fn test() {
     assert_eq!(part1(&data), 374);
     assert_eq!(with_expansion(&data, 10), 1030);
     assert_eq!(with_expansion(&data, 100), 8410);
+
+    let universe = Universe::new(&data, 2);
+    assert_eq!(universe.len(), 9);
+    assert_eq!(universe.distance(4, 8), 9);
+    assert_eq!(universe.distance(0, 6), 15);
+    assert_eq!(universe.distance(2, 5), 17);
+    assert_eq!(universe.distance(7, 8), 5);
+    let pairwise: u128 = (0..universe.len())
+        .flat_map(|a| (0..a).map(move |b| (a, b)))
+        .map(|(a, b)| universe.distance(a, b))
+        .sum();
+    assert_eq!(pairwise, 374);
+
+    // Too big for 64 bits
+    let huge = with_expansion(&data, 1 << 70);
+    assert_eq!(huge, 82 * ((1 << 70) - 1) + 292);
 }
 
 fn main() -> std::io::Result<()>{
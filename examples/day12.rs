@@ -1,9 +1,8 @@
-use std::collections::HashMap;
-
 #[allow(unused)]
-use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
+use adventofcode2023::{get_input,parse_lines,regex_parser,timeit,xorshift};
+use adventofcode2023::viz::{Cell, Frame, Grid};
 
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
 enum Spring {
     Operational,
     Damaged,
@@ -55,92 +54,186 @@ fn parse_input(input: &str) -> Data {
     result
 }
 
-fn count_matches(row: &Row) -> usize {
-    let tot_failed: u32 = row.runs.iter().sum::<usize>() as u32;
-    let _tot_ok = row.springs.len() as u32 - tot_failed;
-    let num_unknown = row.springs.iter().cloned().filter(|&s| s == Spring::Unknown).count() as u32;
-    let num_known_failed = row.springs.iter().cloned().filter(|&s| s == Spring::Damaged).count() as u32;
-    let needed_damaged = tot_failed - num_known_failed;
-
-    let mut count = 0;
-    'main: for mut repl in 0u64..=((1<<num_unknown)-1) {
-        if repl.count_ones() != needed_damaged {
-            continue;
+/// For every state partway along a row - the number of springs placed,
+/// the number of runs completed and the length of the current run of
+/// damaged springs - the number of ways to finish the row.  Filled in from
+/// the end, so the whole row is counted in O(springs * runs * run length).
+/// Counts are exact up to `u128::MAX`; building the table for a row with
+/// more arrangements than that panics rather than wrapping.
+struct Arrangements<'a> {
+    row: &'a Row,
+    max_run: usize,
+    table: Vec<u128>,
+}
+
+impl<'a> Arrangements<'a> {
+    pub fn new(row: &'a Row) -> Arrangements<'a> {
+        let n = row.springs.len();
+        let m = row.runs.len();
+        let max_run = row.runs.iter().cloned().max().unwrap_or(0);
+        let mut result = Arrangements {
+            row,
+            max_run,
+            table: vec![0; (n + 1) * (m + 1) * (max_run + 1)],
+        };
+
+        for j in 0..=m {
+            for r in 0..=max_run {
+                let done = (r == 0 && j == m) ||
+                           (r > 0 && j + 1 == m && r == row.runs[j]);
+                let idx = result.index(n, j, r);
+                result.table[idx] = if done { 1 } else { 0 };
+            }
         }
-        let mut cur_run = 0;
-        let mut counts = row.runs.iter();
-        for &s in &row.springs {
-            let guess = if s == Spring::Unknown {
-                let bit = repl & 1;
-                repl = repl >> 1;
-                if bit == 1 {
-                    Spring::Damaged
-                } else {
-                    Spring::Operational
+        for i in (0..n).rev() {
+            for j in 0..=m {
+                for r in 0..=max_run {
+                    let count = result.next_states(i, j, r)
+                        .into_iter()
+                        .flatten()
+                        .try_fold(0u128, |acc, (_, (j, r))| acc.checked_add(result.get(i + 1, j, r)))
+                        .expect("Arrangement count overflows u128");
+                    let idx = result.index(i, j, r);
+                    result.table[idx] = count;
                 }
-            } else {
-                s
-            };
-            match guess {
-                Spring::Operational => {
-                    if cur_run > 0 {
-                        match (cur_run, counts.next()) {
-                            (_, None) => { continue 'main; }
-                            (a, Some(&b)) => {
-                                if a != b {
-                                    continue 'main;
-                                }
-                            }
+            }
+        }
+        result
+    }
+
+    fn index(&self, i: usize, j: usize, r: usize) -> usize {
+        (i * (self.row.runs.len() + 1) + j) * (self.max_run + 1) + r
+    }
+
+    fn get(&self, i: usize, j: usize, r: usize) -> u128 {
+        self.table[self.index(i, j, r)]
+    }
+
+    // The ways to fill in spring i from state (j, r): operational first,
+    // then damaged, with the state after it.
+    fn next_states(&self, i: usize, j: usize, r: usize) -> [Option<(Spring, (usize, usize))>; 2] {
+        let runs = &self.row.runs;
+        let spring = self.row.springs[i];
+        let operational = if spring == Spring::Damaged {
+            None
+        } else if r == 0 {
+            Some((Spring::Operational, (j, 0)))
+        } else if j < runs.len() && r == runs[j] {
+            Some((Spring::Operational, (j + 1, 0)))
+        } else {
+            None
+        };
+        let damaged = if spring != Spring::Operational && j < runs.len() && r < runs[j] {
+            Some((Spring::Damaged, (j, r + 1)))
+        } else {
+            None
+        };
+        [operational, damaged]
+    }
+
+    pub fn count(&self) -> u128 {
+        self.get(0, 0, 0)
+    }
+
+    /// For each spring, the number of arrangements with it operational and
+    /// the number with it damaged.
+    pub fn cell_counts(&self) -> Vec<(u128, u128)> {
+        let n = self.row.springs.len();
+        let m = self.row.runs.len();
+        // Ways of reaching each (runs done, run length) before spring i
+        let mut prefix = vec![0u128; (m + 1) * (self.max_run + 1)];
+        prefix[0] = 1;
+        let mut result = Vec::with_capacity(n);
+        for i in 0..n {
+            let mut counts = (0, 0);
+            let mut next_prefix = vec![0u128; prefix.len()];
+            for j in 0..=m {
+                for r in 0..=self.max_run {
+                    let ways = prefix[j * (self.max_run + 1) + r];
+                    if ways == 0 {
+                        continue;
+                    }
+                    for (spring, (nj, nr)) in self.next_states(i, j, r).into_iter().flatten() {
+                        // Only follow states which can still finish the row,
+                        // so every product here is at most `count()` and
+                        // can't overflow.
+                        let rest = self.get(i + 1, nj, nr);
+                        if rest == 0 {
+                            continue;
+                        }
+                        next_prefix[nj * (self.max_run + 1) + nr] += ways;
+                        let total = ways * rest;
+                        match spring {
+                            Spring::Operational => counts.0 += total,
+                            _ => counts.1 += total,
                         }
                     }
-                    cur_run = 0;
-                }
-                Spring::Damaged => {
-                    cur_run += 1;
                 }
-                Spring::Unknown => unreachable!()
             }
+            result.push(counts);
+            prefix = next_prefix;
+        }
+        result
+    }
+
+    /// The arrangement at position `k` when they're all listed in order,
+    /// with operational before damaged at the first difference.
+    pub fn nth(&self, mut k: u128) -> Option<Vec<Spring>> {
+        if k >= self.count() {
+            return None;
         }
-        // Check if a current run matches
-        if cur_run > 0 {
-            let next_run = counts.next();
-            if let Some(&r) = next_run {
-                if r != cur_run {
-                    continue 'main;
+        let mut result = Vec::with_capacity(self.row.springs.len());
+        let (mut j, mut r) = (0, 0);
+        for i in 0..self.row.springs.len() {
+            for (spring, (nj, nr)) in self.next_states(i, j, r).into_iter().flatten() {
+                let count = self.get(i + 1, nj, nr);
+                if k < count {
+                    result.push(spring);
+                    (j, r) = (nj, nr);
+                    break;
                 }
-            } else {
-                continue 'main;
+                k -= count;
             }
         }
-        if !counts.next().is_none() {
-            continue 'main;
+        Some(result)
+    }
+
+    /// One arrangement chosen uniformly at random, given a source of
+    /// random 64-bit numbers.
+    pub fn sample(&self, random: &mut impl FnMut() -> u64) -> Option<Vec<Spring>> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        // Reject the top partial block so that every k is equally likely.
+        let limit = u128::MAX - u128::MAX % count;
+        loop {
+            let k = ((random() as u128) << 64) | random() as u128;
+            if k < limit {
+                return self.nth(k % count);
+            }
         }
-        count += 1;
     }
-    count
+}
+
+fn count_arrangements(row: &Row) -> u128 {
+    Arrangements::new(row).count()
 }
 
 timeit!{
-fn part1(data: &Data) -> usize {
-    data.iter()
-        .map(count_matches)
-        .sum()
-}}
-timeit!{
-fn part1_2(data: &Data) -> usize {
+fn part1(data: &Data) -> u128 {
     data.iter()
-        .map(count_matches2)
+        .map(count_arrangements)
         .sum()
 }}
 
-fn expand_part2(row: &Row) -> Row {
+/// The row repeated `factor` times, with unknown springs in between.
+fn unfold(row: &Row, factor: usize) -> Row {
     let mut springs = row.springs.clone();
-    for _ in 0..4 {
+    let mut runs = row.runs.clone();
+    for _ in 1..factor {
         springs.push(Spring::Unknown);
         springs.extend_from_slice(&row.springs);
-    }
-    let mut runs = row.runs.clone();
-    for _ in 0..4 {
         runs.extend_from_slice(&row.runs);
     }
     Row {
@@ -148,109 +241,144 @@ fn expand_part2(row: &Row) -> Row {
     }
 }
 
-fn to_bits<I: Iterator<Item = bool>>(i: I) -> u128 {
-    let mut result = 0u128;
-    for b in i {
-        result <<= 1;
-        if b {
-            result |= 1;
-        }
-    }
-    result
+fn expand_part2(row: &Row) -> Row {
+    unfold(row, 5)
 }
 
-type Cache<'a> = HashMap<(&'a [usize], u128, u128, u128), usize>;
+timeit!{
+fn part2(data: &Data) -> u128 {
+    data.iter()
+        .map(expand_part2)
+        .map(|r| count_arrangements(&r))
+        .sum()
+}}
 
-fn count_placings<'c, 'a:'c>(cache: &'c mut Cache<'a>, runs: &'a [usize], set: u128, maybe: u128, clear: u128) -> usize {
-    if let Some(result) = cache.get(&(runs, set, maybe, clear)) {
-        return *result;
-    }
-//    eprintln!("count_placings({runs:?}, {set:b}, {maybe:b}, {clear:b}");
-    if runs.len() == 0 {
-//        eprintln!("Return early");
-        return if set == 0 { 1 } else { 0 };
-    }
-    let next_piece = runs[0];
-    let piece_bits = (1u128 << next_piece) - 1;
-    let num_bits = 128 - maybe.leading_zeros();
-//    dbg!((next_piece, piece_bits, num_bits));
-
-    // Minimum space to the right including gaps
-    let mut first_pos: u32 = runs[1..].iter().map(|&n| (n as u32) +1).sum();
-
-    let num_bits_known_set = 128 - set.leading_zeros();
-//    dbg!((num_bits_known_set, next_piece));
-    if num_bits_known_set >= next_piece as u32{
-        first_pos = first_pos.max(num_bits_known_set - next_piece as u32)
-    }
+/// A nonogram puzzle: the runs of filled cells in each row and column.
+#[derive(Debug)]
+struct Nonogram {
+    rows: Vec<Vec<usize>>,
+    cols: Vec<Vec<usize>>,
+}
 
-    if num_bits < next_piece as u32 {
-        return 0;
+/// Parse clues in the common `.non` format: `width`/`height` lines, then
+/// `rows` and `columns` sections with one comma-separated clue per line
+/// (`0` or blank for an empty line).  Other keywords are ignored.
+fn parse_nonogram(input: &str) -> Nonogram {
+    let mut width = None;
+    let mut height = None;
+    let mut rows = Vec::new();
+    let mut cols = Vec::new();
+    let mut lines = input.lines().map(str::trim);
+    while let Some(line) = lines.next() {
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        match keyword {
+            "width" => { width = Some(rest.trim().parse().unwrap()); }
+            "height" => { height = Some(rest.trim().parse().unwrap()); }
+            "rows" | "columns" => {
+                let num = if keyword == "rows" { height } else { width }
+                    .expect("Size must come before clues");
+                let clues = lines.by_ref()
+                    .take(num)
+                    .map(|l| l.split(|c: char| c == ',' || c.is_whitespace())
+                              .filter(|s| !s.is_empty())
+                              .map(|s| s.parse().unwrap())
+                              .filter(|&n| n > 0)
+                              .collect())
+                    .collect();
+                if keyword == "rows" { rows = clues; } else { cols = clues; }
+            }
+            _ => {}
+        }
     }
-    let last_pos = num_bits - next_piece as u32;
-
-    let mut count = 0;
-//    dbg!((first_pos, last_pos));
+    assert_eq!(rows.len(), height.unwrap_or(0));
+    assert_eq!(cols.len(), width.unwrap_or(0));
+    Nonogram { rows, cols }
+}
 
-    for shift in first_pos..=last_pos {
-        let shifted = piece_bits << shift;
+type Picture = Vec<Vec<Spring>>;
 
-        if (shifted & clear) != 0 {
-            // Can't overlap any clear bits
-            continue;
+// Solve each line as far as its clue allows given what's known, until
+// nothing changes.  Returns false if some line has no arrangements.
+fn propagate(puzzle: &Nonogram, picture: &mut Picture) -> bool {
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for transposed in [false, true] {
+            let clues = if transposed { &puzzle.cols } else { &puzzle.rows };
+            for (i, runs) in clues.iter().enumerate() {
+                let springs = if transposed {
+                    picture.iter().map(|row| row[i]).collect()
+                } else {
+                    picture[i].clone()
+                };
+                let row = Row { springs, runs: runs.clone() };
+                let arr = Arrangements::new(&row);
+                if arr.count() == 0 {
+                    return false;
+                }
+                for (j, (operational, damaged)) in arr.cell_counts().into_iter().enumerate() {
+                    let known = match (operational, damaged) {
+                        (_, 0) => Spring::Operational,
+                        (0, _) => Spring::Damaged,
+                        _ => continue,
+                    };
+                    let cell = if transposed { &mut picture[j][i] } else { &mut picture[i][j] };
+                    if *cell != known {
+                        *cell = known;
+                        changed = true;
+                    }
+                }
+            }
         }
+    }
+    true
+}
 
-        // The following bit must not be set.
-        if shift > 0 && ((1<<(shift-1)) & set != 0) {
-            continue;
-        }
+/// Solve by line propagation, guessing a cell and backtracking when that
+/// gets stuck.
+fn solve_nonogram(puzzle: &Nonogram) -> Option<Picture> {
+    let picture = vec![vec![Spring::Unknown; puzzle.cols.len()]; puzzle.rows.len()];
+    solve_from(puzzle, picture)
+}
 
-        let next_mask = if shift == 0 {
-            0
-        } else {
-            (1 << (shift -1)) - 1
-        };
-        count += count_placings(cache, &runs[1..],
-                                set & next_mask,
-                                maybe & next_mask,
-                                clear & next_mask);
+fn solve_from(puzzle: &Nonogram, mut picture: Picture) -> Option<Picture> {
+    if !propagate(puzzle, &mut picture) {
+        return None;
+    }
+    let unknown = picture.iter()
+        .enumerate()
+        .find_map(|(y, row)| row.iter().position(|&s| s == Spring::Unknown).map(|x| (x, y)));
+    match unknown {
+        None => Some(picture),
+        Some((x, y)) => {
+            [Spring::Damaged, Spring::Operational]
+                .into_iter()
+                .find_map(|guess| {
+                    let mut attempt = picture.clone();
+                    attempt[y][x] = guess;
+                    solve_from(puzzle, attempt)
+                })
+        }
     }
-//    dbg!(count)
-    cache.insert((runs, set, maybe, clear), count);
-    count
 }
 
-fn count_matches2(row: &Row) -> usize {
-//    eprintln!("{row} len {}", row.springs.len());
-    assert!(row.springs.len() <= 128);
-
-    // Useful bitsets
-    let bits_damaged = to_bits(
-        row.springs
-           .iter()
-           .map(|&s| s == Spring::Damaged));
-    let bits_maybedamaged = to_bits(
-        row.springs
-           .iter()
-           .map(|&s| s != Spring::Operational));
-    let bits_clear = to_bits(
-        row.springs
-           .iter()
-           .map(|&s| s == Spring::Operational));
-
-    let mut cache: Cache = HashMap::new();
-    let result = count_placings(&mut cache, &row.runs, bits_damaged, bits_maybedamaged, bits_clear);
-//    dbg!(result)
-    result
-}
+struct PictureView<'a>(&'a Picture);
 
-timeit!{
-fn part2(data: &Data) -> usize {
-    data.iter()
-        .map(expand_part2)
-        .map(|r| count_matches2(&r))
-        .sum()
-}}
+impl Grid for PictureView<'_> {
+    fn width(&self) -> usize {
+        self.0.first().map(|row| row.len()).unwrap_or(0)
+    }
+    fn height(&self) -> usize {
+        self.0.len()
+    }
+    fn cell(&self, x: usize, y: usize) -> Cell {
+        match self.0[y][x] {
+            Spring::Operational => '.'.into(),
+            Spring::Damaged => '#'.into(),
+            Spring::Unknown => '?'.into(),
+        }
+    }
+}
 
 #[test]
 fn test() {
@@ -263,8 +391,112 @@ fn test() {
     let data = parse_input(&tests);
 
     assert_eq!(part1(&data), 21);
-    assert_eq!(part1_2(&data), 21);
     assert_eq!(part2(&data), 525152);
+
+    let counts = data.iter().map(count_arrangements).collect::<Vec<_>>();
+    assert_eq!(counts, vec![1, 4, 1, 1, 4, 10]);
+    let counts = data.iter()
+        .map(|row| count_arrangements(&unfold(row, 5)))
+        .collect::<Vec<_>>();
+    assert_eq!(counts, vec![1, 16384, 1, 16, 2500, 506250]);
+    // Far more than fits in the old 128-bit masks.
+    assert_eq!(count_arrangements(&unfold(&data[0], 50)), 1);
+    assert!(count_arrangements(&unfold(&data[5], 12)) > 506250);
+
+    // Listing every arrangement gives distinct ones which fit the row.
+    for row in &data {
+        let arr = Arrangements::new(row);
+        let all = (0..arr.count())
+            .map(|k| arr.nth(k).unwrap())
+            .collect::<Vec<_>>();
+        for (k, springs) in all.iter().enumerate() {
+            assert!(fits(row, springs));
+            if k > 0 {
+                assert!(all[k-1] < *springs);
+            }
+        }
+        assert_eq!(arr.nth(arr.count()), None);
+    }
+    assert_eq!(spring_string(&Arrangements::new(&data[5]).nth(0).unwrap()), ".###....##.#");
+
+    let mut rng = xorshift(1);
+    let row = unfold(&data[5], 3);
+    let arr = Arrangements::new(&row);
+    for _ in 0..20 {
+        assert!(fits(&row, &arr.sample(&mut rng).unwrap()));
+    }
+
+    let cells = Arrangements::new(&data[1]).cell_counts();
+    assert_eq!(cells.iter().map(|c| c.1).collect::<Vec<_>>(),
+               vec![0, 2, 2, 0, 0, 2, 2, 0, 0, 0, 4, 4, 4, 0]);
+    assert!(cells.iter().all(|c| c.0 + c.1 == 4));
+}
+
+#[test]
+#[should_panic(expected = "overflows u128")]
+fn test_overflow() {
+    // C(200, 60) ways to place sixty single damaged springs, around 10^51.
+    let row = format!("{} {}", "?".repeat(259), vec!["1"; 60].join(","));
+    count_arrangements(&parse_input(&row)[0]);
+}
+
+#[test]
+fn test_nonogram() {
+    let diamond = r#"width 5
+height 5
+
+rows
+1
+3
+5
+3
+1
+
+columns
+1
+3
+5
+3
+1"#;
+    let puzzle = parse_nonogram(diamond);
+    let picture = solve_nonogram(&puzzle).unwrap();
+    assert_eq!(Frame::capture(&PictureView(&picture)).to_text(),
+               "..#..\n.###.\n#####\n.###.\n..#..\n");
+
+    // Line solving alone makes no progress on this one.
+    let ambiguous = "width 4\nheight 4\nrows\n1,1\n1,1\n1,1\n1,1\ncolumns\n1 1\n1 1\n1 1\n1 1\n";
+    let puzzle = parse_nonogram(ambiguous);
+    let picture = solve_nonogram(&puzzle).unwrap();
+    for (runs, row) in puzzle.rows.iter().zip(&picture) {
+        assert!(fits(&Row { springs: vec![Spring::Unknown; 4], runs: runs.clone() }, row));
+    }
+    for (x, runs) in puzzle.cols.iter().enumerate() {
+        let col = picture.iter().map(|row| row[x]).collect::<Vec<_>>();
+        assert!(fits(&Row { springs: vec![Spring::Unknown; 4], runs: runs.clone() }, &col));
+    }
+
+    let impossible = "width 2\nheight 2\nrows\n2\n0\ncolumns\n0\n1\n";
+    assert!(solve_nonogram(&parse_nonogram(impossible)).is_none());
+}
+
+#[cfg(test)]
+fn spring_string(springs: &[Spring]) -> String {
+    Row { springs: springs.to_vec(), runs: vec![] }
+        .to_string()
+        .trim_end()
+        .to_string()
+}
+
+// Whether a fully known arrangement is consistent with a row.
+#[cfg(test)]
+fn fits(row: &Row, springs: &[Spring]) -> bool {
+    let runs = springs.split(|&s| s == Spring::Operational)
+        .map(|run| run.len())
+        .filter(|&len| len > 0)
+        .collect::<Vec<_>>();
+    springs.len() == row.springs.len() &&
+        runs == row.runs &&
+        springs.iter().zip(&row.springs).all(|(&s, &r)| r == Spring::Unknown || s == r)
 }
 
 fn main() -> std::io::Result<()>{
@@ -278,5 +510,27 @@ fn main() -> std::io::Result<()>{
     // Part 2
     println!("{}", part2(&data));
 
+    let mut args = std::env::args().skip_while(|arg| arg != "--nonogram");
+    if args.next().is_some() {
+        let filename = args.next().expect("--nonogram needs a filename");
+        let puzzle = parse_nonogram(&std::fs::read_to_string(filename)?);
+        match solve_nonogram(&puzzle) {
+            Some(picture) => print!("{}", Frame::capture(&PictureView(&picture)).to_text()),
+            None => println!("No solution"),
+        }
+    }
+
+    // Show one of the arrangements of each row, chosen at random.
+    if std::env::args().any(|arg| arg == "--sample") {
+        let mut rng = xorshift(0x2023_1212);
+        for row in &data {
+            let arr = Arrangements::new(row);
+            if let Some(springs) = arr.sample(&mut rng) {
+                let sample = Row { springs, runs: row.runs.clone() };
+                println!("{}  <- {} of {}", sample, row, arr.count());
+            }
+        }
+    }
+
     Ok(())
 }
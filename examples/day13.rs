@@ -1,131 +1,148 @@
 #[allow(unused)]
 use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
+use adventofcode2023::bits::BitRow;
 
-type Row = u32;
+type Pos = (usize, usize);
 
-struct Field {
-    num_cols: usize,
-    rows: Vec<u32>,
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Axis {
+    // Between columns
+    Vertical,
+    // Between rows
+    Horizontal,
+    // From top-left to bottom-right
+    Diagonal,
+    // From top-right to bottom-left
+    AntiDiagonal,
 }
 
-fn nearly_eq(a: u32, b: u32) -> bool {
-    let diffs = a ^ b;
-    let ones = diffs.count_ones();
-    ones <= 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Reflection {
+    axis: Axis,
+    // Columns to the left or rows above the line; 0 for diagonals
+    pos: usize,
+    // Pairs of mirrored cells which don't match
+    smudges: Vec<(Pos, Pos)>,
 }
 
-impl Field {
-    pub fn col(&self, i: usize) -> u32 {
-        let mut result = 0;
-        for r in &self.rows {
-            result <<= 1;
-            result |= (r >> i) & 1;
+impl Reflection {
+    pub fn summary(&self) -> usize {
+        match self.axis {
+            Axis::Vertical => self.pos,
+            Axis::Horizontal => 100 * self.pos,
+            Axis::Diagonal | Axis::AntiDiagonal => 0,
         }
-        result
     }
-    pub fn vert_sym(&self) -> Option<usize> {
-        'i: for i in 0..self.num_cols {
-            if self.col(i) == self.col(i+1) {
-                for offs in 0.. {
-                    if offs > i {
-                        return Some(self.num_cols - i - 1);
-                    } else if i+offs+1 >= self.num_cols {
-                        return Some(self.num_cols - i - 1);
-                    }
-                    if self.col(i-offs) != self.col(i+offs+1) {
-                        continue 'i;
-                    }
+}
+
+struct Field {
+    rows: Vec<BitRow>,
+    cols: Vec<BitRow>,
+}
+
+// Every place a list of lines can be folded so that exactly `smudges`
+// bits differ, with the fold position and the (line, bit) pairs which
+// differ.
+fn mirror_lines(lines: &[BitRow], smudges: usize) -> Vec<(usize, Vec<(Pos, Pos)>)> {
+    let mut result = Vec::new();
+    'p: for p in 1..lines.len() {
+        let mut diffs = Vec::new();
+        for offs in 0..p.min(lines.len() - p) {
+            let (a, b) = (p - offs - 1, p + offs);
+            for bit in (&lines[a] ^ &lines[b]).iter_ones() {
+                diffs.push(((a, bit), (b, bit)));
+                if diffs.len() > smudges {
+                    continue 'p;
                 }
             }
         }
-        None
-        
-    }
-    pub fn horz_sym(&self) -> Option<usize> {
-        'i: for i in 0..(self.rows.len()-1) {
-            if self.rows[i] == self.rows[i+1] {
-                for offs in 0.. {
-                    if offs > i {
-                        return Some(i+1);
-                    } else if i+offs+1 >= self.rows.len() {
-                        return Some(i+1);
-                    }
-                    if self.rows[i-offs] != self.rows[i+offs+1] {
-                        continue 'i;
-                    }
-                }
-            }
+        if diffs.len() == smudges {
+            result.push((p, diffs));
         }
-        None
     }
-    pub fn vert_sym_smudged(&self) -> Option<usize> {
-        'i: for i in 0..self.num_cols {
-            if nearly_eq(self.col(i), self.col(i+1)) {
-                let mut smudges = 0;
-                for offs in 0.. {
-                    if offs > i {
-                        if smudges == 1 {
-                            return Some(self.num_cols - i - 1);
-                        } else {
-                            continue 'i;
-                        }
-                    } else if i+offs+1 >= self.num_cols {
-                        if smudges == 1 {
-                            return Some(self.num_cols - i - 1);
-                        } else {
-                            continue 'i;
-                        }
-                    }
-                    let diffs = self.col(i-offs) ^ self.col(i+offs+1);
-                    smudges += diffs.count_ones();
-                    if smudges > 1 {
-                        continue 'i;
-                    }
-                }
-            }
-        }
-        None
-        
+    result
+}
+
+impl Field {
+    pub fn num_cols(&self) -> usize {
+        self.cols.len()
     }
-    pub fn horz_sym_smudged(&self) -> Option<usize> {
-        'i: for i in 0..(self.rows.len()-1) {
-            if nearly_eq(self.rows[i], self.rows[i+1]) {
-                let mut smudges = 0;
-                for offs in 0.. {
-                    if offs > i {
-                        if smudges == 1 {
-                            return Some(i+1);
-                        } else {
-                            continue 'i;
-                        }
-                    } else if i+offs+1 >= self.rows.len() {
-                        if smudges == 1 {
-                            return Some(i+1);
-                        } else {
-                            continue 'i;
-                        }
-                    }
-                    let diffs = self.rows[i-offs] ^ self.rows[i+offs+1];
-                    smudges += diffs.count_ones();
-                    if smudges > 1 {
-                        continue 'i;
+
+    #[cfg(test)]
+    pub fn col(&self, i: usize) -> &BitRow {
+        &self.cols[i]
+    }
+
+    pub fn vert_sym(&self, smudges: usize) -> Vec<Reflection> {
+        mirror_lines(&self.cols, smudges)
+            .into_iter()
+            .map(|(pos, diffs)| Reflection {
+                axis: Axis::Vertical,
+                pos,
+                smudges: diffs,
+            })
+            .collect()
+    }
+
+    pub fn horz_sym(&self, smudges: usize) -> Vec<Reflection> {
+        mirror_lines(&self.rows, smudges)
+            .into_iter()
+            .map(|(pos, diffs)| Reflection {
+                axis: Axis::Horizontal,
+                pos,
+                smudges: diffs.into_iter()
+                              .map(|((y1, x1), (y2, x2))| ((x1, y1), (x2, y2)))
+                              .collect(),
+            })
+            .collect()
+    }
+
+    /// Reflections about either diagonal, which only square fields can
+    /// have.
+    pub fn diag_sym(&self, smudges: usize) -> Vec<Reflection> {
+        let n = self.rows.len();
+        if n != self.num_cols() {
+            return vec![];
+        }
+        let mut result = Vec::new();
+        for (axis, mirror) in [(Axis::Diagonal, (|x, y, _| (y, x)) as fn(usize, usize, usize) -> Pos),
+                               (Axis::AntiDiagonal, |x, y, n| (n - 1 - y, n - 1 - x))] {
+            let mut diffs = Vec::new();
+            for y in 0..n {
+                for x in 0..n {
+                    let (mx, my) = mirror(x, y, n);
+                    // Only look at each pair once, from the lower side
+                    if (my, mx) > (y, x) && self.rows[y].get(x) != self.rows[my].get(mx) {
+                        diffs.push(((x, y), (mx, my)));
                     }
                 }
             }
+            if diffs.len() == smudges {
+                result.push(Reflection { axis, pos: 0, smudges: diffs });
+            }
         }
-        None
+        result
     }
-}
 
-fn make_row(s: &str) -> Row {
-    let mut result = Default::default();
-    for c in s.chars() {
-        result = result << 1;
-        if c == '#' {
-            result |= 1;
-        }
+    /// All the reflection lines with exactly `smudges` mismatches.
+    pub fn reflections(&self, smudges: usize) -> Vec<Reflection> {
+        let mut result = self.vert_sym(smudges);
+        result.extend(self.horz_sym(smudges));
+        result.extend(self.diag_sym(smudges));
+        result
+    }
+
+    pub fn summary(&self, smudges: usize) -> usize {
+        self.vert_sym(smudges)
+            .into_iter()
+            .chain(self.horz_sym(smudges))
+            .map(|r| r.summary())
+            .sum()
     }
-    result
+}
+
+fn make_row(s: &str) -> BitRow {
+    BitRow::from_bools(s.chars().map(|c| c == '#'))
 }
 
 type Data = Vec<Field>;
@@ -133,40 +150,28 @@ fn parse_input(input: &str) -> Data {
     let fieldstrs = input.split("\n\n");
     let mut result = Vec::new();
     for fieldstr in fieldstrs {
-        let num_cols = fieldstr.lines().next().unwrap().len();
-        let rows = fieldstr.lines()
+        let rows: Vec<BitRow> = fieldstr.lines()
             .map(make_row)
             .collect();
-        result.push(Field { num_cols, rows });
+        let cols = (0..rows[0].len())
+            .map(|x| BitRow::from_bools(rows.iter().map(|row| row.get(x))))
+            .collect();
+        result.push(Field { rows, cols });
     }
     result
 }
 
 timeit!{
 fn part1(data: &Data) -> usize {
-    let mut result = 0;
-    for field in data {
-        if let Some(n) = field.vert_sym() {
-            result += n;
-        }
-        if let Some(n) = field.horz_sym() {
-            result += 100 * n;
-        }
-    }
-    result
+    data.iter()
+        .map(|field| field.summary(0))
+        .sum()
 }}
 timeit!{
 fn part2(data: &Data) -> usize {
-    let mut result = 0;
-    for field in data {
-        if let Some(n) = field.vert_sym_smudged() {
-            result += n;
-        }
-        if let Some(n) = field.horz_sym_smudged() {
-            result += 100 * n;
-        }
-    }
-    result
+    data.iter()
+        .map(|field| field.summary(1))
+        .sum()
 }}
 
 #[test]
@@ -190,6 +195,33 @@ fn test() {
 
     assert_eq!(part1(&data), 405);
     assert_eq!(part2(&data), 400);
+
+    assert_eq!(data[0].reflections(0),
+               vec![Reflection { axis: Axis::Vertical, pos: 5, smudges: vec![] }]);
+    assert_eq!(data[0].reflections(1),
+               vec![Reflection { axis: Axis::Horizontal, pos: 3, smudges: vec![((0, 0), (0, 5))] }]);
+    assert_eq!(data[1].reflections(1),
+               vec![Reflection { axis: Axis::Horizontal, pos: 1, smudges: vec![((4, 0), (4, 1))] }]);
+    assert_eq!(data[0].col(0), &make_row("#.##..#"));
+
+    // Wider than the old 32-bit rows, with several smudged reflections.
+    let wide = parse_input(&format!("{0}{0}\n{0}{1}\n{1}{0}\n{0}{0}",
+                                    "#.#...##..#.#....##.",
+                                    "#.#...##..#.##...##."));
+    assert_eq!(wide[0].num_cols(), 40);
+    assert_eq!(wide[0].horz_sym(0), vec![]);
+    assert_eq!(wide[0].horz_sym(1).iter().map(|r| r.pos).collect::<Vec<_>>(), vec![1, 3]);
+    assert_eq!(wide[0].horz_sym(1)[1].smudges, vec![((13, 2), (13, 3))]);
+    let two = wide[0].horz_sym(2);
+    assert_eq!(two.len(), 1);
+    assert_eq!(two[0].pos, 2);
+    assert_eq!(two[0].smudges, vec![((13, 1), (13, 2)), ((33, 1), (33, 2))]);
+
+    let square = parse_input("#.#\n.#.\n#..");
+    let diag = square[0].diag_sym(0);
+    assert_eq!(diag, vec![Reflection { axis: Axis::Diagonal, pos: 0, smudges: vec![] }]);
+    assert_eq!(square[0].diag_sym(1),
+               vec![Reflection { axis: Axis::AntiDiagonal, pos: 0, smudges: vec![((0, 0), (2, 2))] }]);
 }
 
 fn main() -> std::io::Result<()>{
@@ -203,5 +235,20 @@ fn main() -> std::io::Result<()>{
     // Part 2
     println!("{}", part2(&data));
 
+    // Every reflection with the given number of smudges
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--reflections" {
+            let smudges = args.next()
+                .map(|s| s.parse().expect("--reflections needs a number"))
+                .unwrap_or(1);
+            for (i, field) in data.iter().enumerate() {
+                for r in field.reflections(smudges) {
+                    println!("field {}: {:?} at {} smudges {:?}", i, r.axis, r.pos, r.smudges);
+                }
+            }
+        }
+    }
+
     Ok(())
 }
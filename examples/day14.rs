@@ -1,7 +1,11 @@
 use std::collections::{HashMap};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io;
 
 #[allow(unused)]
 use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
+use adventofcode2023::viz::{self, Cell, Colour, Control, Grid, Viewer};
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash)]
 enum Space {
@@ -28,149 +32,383 @@ fn parse_input(input: &str) -> Data {
     .collect()
 }
 
-fn iter_col(data: &Data, c: usize) -> impl Iterator<Item=Space> + '_ {
-    data.iter()
-        .map(move |row| row[c])
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Dir {
+    North,
+    West,
+    South,
+    East,
 }
 
-timeit!{
-fn part1(data: &Data) -> usize {
-    let mut sum = 0;
-    for col in 0..data[0].len() {
-        let height = data.len();
-        let mut start_load = height;
-        for (i, space) in iter_col(data, col).enumerate() {
-            match space {
-                Space::Empty => { }
-                Space::Round => {
-                    sum += start_load;
-                    start_load -= 1;
-                }
-                Space::Square => {
-                    start_load = height - i - 1;
-                }
-            }
-        }
+// The order of tilts in a spin cycle
+const SPIN: [Dir; 4] = [Dir::North, Dir::West, Dir::South, Dir::East];
+
+// A run of open cells between cubes or edges, as flat cell indices
+// starting from the end which rocks roll towards.
+#[derive(Copy, Clone, Debug)]
+struct Segment {
+    start: usize,
+    step: isize,
+    len: usize,
+}
+
+impl Segment {
+    fn cells(self) -> impl Iterator<Item=usize> {
+        (0..self.len).map(move |i| (self.start as isize + self.step * i as isize) as usize)
     }
-    sum
-}}
+}
 
-// Tilt to N and rotate by 90 deg
-fn turn(data: &Data) -> Data {
-    let mut result = Vec::new();
-    for x in 0..data[0].len() {
-        let mut row = Vec::new();
-
-        let mut rocks = 0;
-        let mut spaces = 0;
-        for space in iter_col(data, x) {
-            match space {
-                Space::Empty => {
-                    spaces += 1;
-                }
-                Space::Round => {
-                    rocks += 1;
-                }
-                Space::Square => {
-                    for _ in 0..rocks {
-                        row.push(Space::Round);
+/// The round rocks after a tilt, which is just how many ended up in each
+/// segment for that direction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Tilted {
+    dir: Dir,
+    counts: Vec<u32>,
+}
+
+impl Tilted {
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.dir.hash(&mut hasher);
+        self.counts.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+const NO_SEGMENT: u32 = u32::MAX;
+
+/// The fixed layout of the cube rocks, with the segments for each tilt
+/// direction worked out up front.
+struct Platform {
+    width: usize,
+    height: usize,
+    squares: Vec<bool>,
+    // The round rocks as given, before any tilting
+    initial: Vec<usize>,
+    segments: [Vec<Segment>; 4],
+    // Which segment of each direction a cell is in
+    segment_of: [Vec<u32>; 4],
+}
+
+impl Platform {
+    pub fn new(data: &Data) -> Platform {
+        let height = data.len();
+        let width = data[0].len();
+        let cells = data.iter().flatten().copied().collect::<Vec<_>>();
+        let squares = cells.iter().map(|&s| s == Space::Square).collect::<Vec<_>>();
+        let initial = (0..cells.len()).filter(|&i| cells[i] == Space::Round).collect();
+
+        let mut segments: [Vec<Segment>; 4] = Default::default();
+        let mut segment_of: [Vec<u32>; 4] = std::array::from_fn(|_| vec![NO_SEGMENT; cells.len()]);
+        for dir in SPIN {
+            // Each line is walked starting from the end rocks roll to.
+            let (lines, len, step) = match dir {
+                Dir::North => (width, height, width as isize),
+                Dir::South => (width, height, -(width as isize)),
+                Dir::West => (height, width, 1),
+                Dir::East => (height, width, -1),
+            };
+            let index = |line: usize, i: usize| match dir {
+                Dir::North => i * width + line,
+                Dir::South => (height - 1 - i) * width + line,
+                Dir::West => line * width + i,
+                Dir::East => line * width + width - 1 - i,
+            };
+            let segs = &mut segments[dir as usize];
+            for line in 0..lines {
+                let mut i = 0;
+                while i < len {
+                    if squares[index(line, i)] {
+                        i += 1;
+                        continue;
                     }
-                    for _ in 0..spaces {
-                        row.push(Space::Empty);
+                    let start = index(line, i);
+                    let mut run = 0;
+                    while i < len && !squares[index(line, i)] {
+                        segment_of[dir as usize][index(line, i)] = segs.len() as u32;
+                        run += 1;
+                        i += 1;
                     }
-                    row.push(Space::Square);
-                    rocks = 0;
-                    spaces = 0;
+                    segs.push(Segment { start, step, len: run });
                 }
             }
         }
-        for _ in 0..rocks {
-            row.push(Space::Round);
+        Platform { width, height, squares, initial, segments, segment_of }
+    }
+
+    /// Tilt rocks at the given cells, only counting how many land in each
+    /// segment.
+    pub fn tilt<I: IntoIterator<Item=usize>>(&self, rocks: I, dir: Dir) -> Tilted {
+        let segment_of = &self.segment_of[dir as usize];
+        let mut counts = vec![0; self.segments[dir as usize].len()];
+        for cell in rocks {
+            counts[segment_of[cell] as usize] += 1;
+        }
+        Tilted { dir, counts }
+    }
+
+    /// The cells with round rocks in a tilted state.
+    pub fn rocks<'a>(&'a self, tilted: &'a Tilted) -> impl Iterator<Item=usize> + 'a {
+        self.segments[tilted.dir as usize]
+            .iter()
+            .zip(&tilted.counts)
+            .flat_map(|(seg, &n)| seg.cells().take(n as usize))
+    }
+
+    pub fn spin<I: IntoIterator<Item=usize>>(&self, rocks: I) -> Tilted {
+        let mut tilted = self.tilt(rocks, SPIN[0]);
+        for dir in &SPIN[1..] {
+            tilted = self.tilt(self.rocks(&tilted), *dir);
         }
-        for _ in 0..spaces {
-            row.push(Space::Empty);
+        tilted
+    }
+
+    /// The load on the edge in direction `towards`.
+    pub fn load<I: IntoIterator<Item=usize>>(&self, rocks: I, towards: Dir) -> usize {
+        rocks.into_iter()
+            .map(|cell| {
+                let (x, y) = (cell % self.width, cell / self.width);
+                match towards {
+                    Dir::North => self.height - y,
+                    Dir::South => y + 1,
+                    Dir::West => self.width - x,
+                    Dir::East => x + 1,
+                }
+            })
+            .sum()
+    }
+
+    /// The round rocks after `spins` spin cycles, skipping ahead once the
+    /// states start repeating.
+    pub fn after_spins(&self, spins: usize) -> Vec<usize> {
+        if spins == 0 {
+            return self.initial.clone();
+        }
+        let mut history = SpinHistory::default();
+        let mut tilted = self.spin(self.initial.iter().copied());
+        loop {
+            let n = history.states.len() + 1;
+            if n == spins {
+                return self.rocks(&tilted).collect();
+            }
+            if let Some(prev) = history.record(tilted) {
+                let period = n - prev;
+                let same = &history.states[prev - 1 + (spins - prev) % period];
+                return self.rocks(same).collect();
+            }
+            tilted = self.spin(self.rocks(history.states.last().unwrap()));
         }
+    }
 
-        row.reverse();
-        result.push(row);
+    pub fn render<I: IntoIterator<Item=usize>>(&self, rocks: I) -> Data {
+        let mut result = (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| if self.squares[y * self.width + x] { Space::Square } else { Space::Empty })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Data>();
+        for cell in rocks {
+            result[cell / self.width][cell % self.width] = Space::Round;
+        }
+        result
     }
-    
-    result
 }
 
-fn cycle(data: &Data) -> Data {
-//    println!("Cycle...");
-//    print_field(data);
-    let mut newdata = turn(data);
-//    print_field(&newdata);
-    newdata = turn(&newdata);
-//    print_field(&newdata);
-    newdata = turn(&newdata);
-//    print_field(&newdata);
-    newdata = turn(&newdata);
-//    print_field(&newdata);
-//    println!("End cycle");
-    newdata
+/// The states after each spin, numbered from 1, looked up by fingerprint.
+#[derive(Default)]
+struct SpinHistory {
+    // Every spin with each fingerprint, as different states can share one
+    seen: HashMap<u64, Vec<usize>>,
+    states: Vec<Tilted>,
 }
 
-#[allow(unused)]
-fn print_field(data: &Data) {
-    for row in data {
-        for c in row {
-            match c {
-                Space::Empty => {
-                    print!(".");
-                }
-                Space::Round => {
-                    print!("O");
-                }
-                Space::Square => {
-                    print!("#");
-                }
-            }
+impl SpinHistory {
+    /// Add the next state, returning which earlier spin it repeats if any.
+    pub fn record(&mut self, tilted: Tilted) -> Option<usize> {
+        let spins = self.seen.entry(tilted.fingerprint()).or_default();
+        if let Some(&prev) = spins.iter().find(|&&prev| self.states[prev - 1] == tilted) {
+            return Some(prev);
         }
-        println!("");
+        self.states.push(tilted);
+        spins.push(self.states.len());
+        None
     }
-    println!("");
 }
 
-fn load(data: &Data) -> usize {
-    let mut sum = 0;
-    for (i, row) in data.iter().enumerate() {
-        let weight = data.len() - i;
-        sum += weight * row.iter().filter(|&s| *s == Space::Round).count();
+timeit!{
+fn part1(data: &Data) -> usize {
+    let platform = Platform::new(data);
+    let tilted = platform.tilt(platform.initial.iter().copied(), Dir::North);
+    platform.load(platform.rocks(&tilted), Dir::North)
+}}
+
+// View of the platform for drawing.
+struct Field<'a>(&'a Data);
+
+impl Grid for Field<'_> {
+    fn width(&self) -> usize {
+        self.0[0].len()
+    }
+    fn height(&self) -> usize {
+        self.0.len()
+    }
+    fn cell(&self, x: usize, y: usize) -> Cell {
+        match self.0[y][x] {
+            Space::Empty => '.'.into(),
+            Space::Round => Cell::from('O').fg(Colour::YELLOW),
+            Space::Square => '#'.into(),
+        }
     }
-    sum
 }
 
 timeit!{
 fn part2(data: &Data) -> usize {
-    let mut cache: HashMap<Data, usize> = Default::default();
-    let target = 1000000000;
-    let mut i = 0;
-    let mut curdata = data.clone();
-    loop {
-        curdata = cycle(&curdata);
-//        print_field(&curdata);
-
-        i += 1;
-//        println!("After {i}: {}", load(&curdata));
-
-        match cache.get_mut(&curdata) {
-            Some(v) => {
-//                println!("Found repeat {v} => {i}, val {}", load(&curdata));
-                let dist = i - *v;
-                if (target - i) % dist == 0 {
-                    return load(&curdata);
+    let platform = Platform::new(data);
+    platform.load(platform.after_spins(1000000000), Dir::North)
+}}
+
+/// Show each spin cycle until the platform gets back to a state it's been
+/// in before.
+fn visualise(data: &Data, viewer: &mut dyn Viewer) -> io::Result<()> {
+    let platform = Platform::new(data);
+    let mut history = SpinHistory::default();
+    let mut rocks = platform.initial.clone();
+    let mut repeat = None;
+    for i in 0.. {
+        let mut status = format!("After {} cycles: load {}", i, platform.load(rocks.iter().copied(), Dir::North));
+        if let Some(prev) = repeat {
+            status.push_str(&format!(", same as after {}", prev));
+        }
+        let field = platform.render(rocks.iter().copied());
+        if viewer.show_grid(&Field(&field), &status)? == Control::Quit || repeat.is_some() {
+            break;
+        }
+        let tilted = platform.spin(rocks);
+        rocks = platform.rocks(&tilted).collect();
+        repeat = history.record(tilted);
+    }
+    Ok(())
+}
+
+// The original grid-turning versions, for checking against `Platform`.
+#[cfg(test)]
+mod reference {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn iter_col(data: &Data, c: usize) -> impl Iterator<Item=Space> + '_ {
+        data.iter()
+            .map(move |row| row[c])
+    }
+
+    pub fn part1_scan(data: &Data) -> usize {
+        let mut sum = 0;
+        for col in 0..data[0].len() {
+            let height = data.len();
+            let mut start_load = height;
+            for (i, space) in iter_col(data, col).enumerate() {
+                match space {
+                    Space::Empty => { }
+                    Space::Round => {
+                        sum += start_load;
+                        start_load -= 1;
+                    }
+                    Space::Square => {
+                        start_load = height - i - 1;
+                    }
                 }
             }
-            None => {
-                cache.insert(curdata.clone(), i);
+        }
+        sum
+    }
+
+    // Tilt to N and rotate by 90 deg
+    fn turn(data: &Data) -> Data {
+        let mut result = Vec::new();
+        for x in 0..data[0].len() {
+            let mut row = Vec::new();
+
+            let mut rocks = 0;
+            let mut spaces = 0;
+            for space in iter_col(data, x) {
+                match space {
+                    Space::Empty => {
+                        spaces += 1;
+                    }
+                    Space::Round => {
+                        rocks += 1;
+                    }
+                    Space::Square => {
+                        for _ in 0..rocks {
+                            row.push(Space::Round);
+                        }
+                        for _ in 0..spaces {
+                            row.push(Space::Empty);
+                        }
+                        row.push(Space::Square);
+                        rocks = 0;
+                        spaces = 0;
+                    }
+                }
+            }
+            for _ in 0..rocks {
+                row.push(Space::Round);
+            }
+            for _ in 0..spaces {
+                row.push(Space::Empty);
             }
+
+            row.reverse();
+            result.push(row);
         }
-        assert!(i < target);
+
+        result
     }
-}}
+
+    pub fn cycle(data: &Data) -> Data {
+        let mut newdata = turn(data);
+        newdata = turn(&newdata);
+        newdata = turn(&newdata);
+        newdata = turn(&newdata);
+        newdata
+    }
+
+    fn load(data: &Data) -> usize {
+        let mut sum = 0;
+        for (i, row) in data.iter().enumerate() {
+            let weight = data.len() - i;
+            sum += weight * row.iter().filter(|&s| *s == Space::Round).count();
+        }
+        sum
+    }
+
+    pub fn part2_turn(data: &Data) -> usize {
+        let mut cache: HashMap<Data, usize> = Default::default();
+        let target = 1000000000;
+        let mut i = 0;
+        let mut curdata = data.clone();
+        loop {
+            curdata = cycle(&curdata);
+
+            i += 1;
+
+            match cache.get_mut(&curdata) {
+                Some(v) => {
+                    let dist = i - *v;
+                    if (target - i) % dist == 0 {
+                        return load(&curdata);
+                    }
+                }
+                None => {
+                    cache.insert(curdata.clone(), i);
+                }
+            }
+            assert!(i < target);
+        }
+    }
+}
 
 #[test]
 fn test() {
@@ -188,6 +426,47 @@ O.#..O.#.#
 
     assert_eq!(part1(&data), 136);
     assert_eq!(part2(&data), 64);
+
+    assert_eq!(reference::part1_scan(&data), 136);
+    assert_eq!(reference::part2_turn(&data), 64);
+    assert_eq!(viz::Frame::capture(&Field(&data)).to_text(), format!("{}\n", tests));
+
+    let platform = Platform::new(&data);
+    let mut old = data.clone();
+    for spins in 0..10 {
+        assert!(platform.render(platform.after_spins(spins)) == old);
+        old = reference::cycle(&old);
+    }
+    assert_eq!(platform.load(platform.after_spins(1), Dir::North), 87);
+    let loads = SPIN.map(|dir| platform.load(platform.initial.iter().copied(), dir));
+    assert_eq!(loads, [104, 121, 94, 77]);
+    assert_eq!(platform.load(platform.after_spins(1000000000), Dir::East), 129);
+
+    // A different state with the same fingerprint isn't taken as a repeat.
+    let first = platform.spin(platform.initial.iter().copied());
+    let second = platform.spin(platform.rocks(&first));
+    let mut history = SpinHistory::default();
+    assert_eq!(history.record(first.clone()), None);
+    history.seen.insert(second.fingerprint(), vec![1]);
+    assert_eq!(history.record(second.clone()), None);
+    assert_eq!(history.record(second), Some(2));
+    assert_eq!(history.record(first), Some(1));
+
+    // Much bigger than the puzzle input
+    let big = (0..8)
+        .flat_map(|_| tests.lines())
+        .map(|line| line.repeat(8))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let big = parse_input(&big);
+    assert_eq!(part2(&big), reference::part2_turn(&big));
+
+    let mut dump = Vec::new();
+    visualise(&data, &mut viz::TextDump::new(&mut dump)).unwrap();
+    let dump = String::from_utf8(dump).unwrap();
+    let headers = dump.lines().filter(|l| l.starts_with("---")).collect::<Vec<_>>();
+    assert_eq!(headers.len(), 11);
+    assert_eq!(headers[10], "--- frame 10: After 10 cycles: load 69, same as after 3");
 }
 
 fn main() -> std::io::Result<()>{
@@ -201,5 +480,9 @@ fn main() -> std::io::Result<()>{
     // Part 2
     println!("{}", part2(&data));
 
+    if let Some(mut viewer) = viz::viewer_from_args()? {
+        visualise(&data, viewer.as_mut())?;
+    }
+
     Ok(())
 }
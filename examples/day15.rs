@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 
 #[allow(unused)]
 use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
@@ -23,54 +24,142 @@ fn part1(data: &Data) -> usize {
         .map(|s| hash(s.as_bytes()) as usize)
         .sum()
 }}
-timeit!{
-fn part2(data: &Data) -> usize {
-    let mut boxes: Vec<Vec<(&str, usize)>> = Vec::new();
-    for _ in 0..256 {
-        boxes.push(Vec::new());
-    }
-
-    for insn in data {
-        if insn.ends_with('-') {
-            // Remove a lens
-            let name = &insn[0..(insn.len() - 1)];
-            let boxnum = hash(name.as_bytes()) as usize;
-            let bx = &mut boxes[boxnum];
-            let mut idx = None; 
-            for (i, (n, _v)) in bx.iter().enumerate() {
-                if *n == name {
-                    idx = Some(i);
-                    break;
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Step<'a> {
+    Remove(&'a str),
+    Insert(&'a str, usize),
+}
+
+fn parse_step(s: &str) -> Step<'_> {
+    if let Some(label) = s.strip_suffix('-') {
+        Step::Remove(label)
+    } else {
+        let (label, focal) = s.split_once('=').unwrap();
+        Step::Insert(label, focal.parse().unwrap())
+    }
+}
+
+#[derive(Default)]
+struct LensBox<'a> {
+    // Lenses in the order they went in, with gaps where they were taken out
+    slots: Vec<Option<(&'a str, usize)>>,
+    index: HashMap<&'a str, usize>,
+}
+
+impl<'a> LensBox<'a> {
+    fn lenses(&self) -> impl Iterator<Item=(&'a str, usize)> + '_ {
+        self.slots.iter().flatten().copied()
+    }
+
+    fn remove(&mut self, label: &str) {
+        if let Some(i) = self.index.remove(label) {
+            self.slots[i] = None;
+            // Squeeze out the gaps once they're most of the box
+            if self.index.len() * 2 < self.slots.len() {
+                self.slots.retain(Option::is_some);
+                for (i, (label, _)) in self.lenses().enumerate().collect::<Vec<_>>() {
+                    self.index.insert(label, i);
                 }
             }
-            if let Some(idx) = idx {
-                bx.remove(idx);
+        }
+    }
+
+    fn insert(&mut self, label: &'a str, focal: usize) {
+        match self.index.get(label) {
+            Some(&i) => self.slots[i] = Some((label, focal)),
+            None => {
+                self.index.insert(label, self.slots.len());
+                self.slots.push(Some((label, focal)));
             }
-        } else {
-            let (name, v) = insn.split_once('=').unwrap();
-            let boxnum = hash(name.as_bytes()) as usize;
-            let pow = v.parse().unwrap();
-            let bx = &mut boxes[boxnum];
-            let mut found = false;
-            for (n, v) in bx.iter_mut() {
-                if *n == name {
-                    *v = pow;
-                    found = true;
-                    break;
-                }
+        }
+    }
+}
+
+/// The 256 boxes of lenses, filled by the HASHMAP procedure.
+struct LensBoxes<'a> {
+    boxes: Vec<LensBox<'a>>,
+}
+
+impl<'a> LensBoxes<'a> {
+    pub fn new() -> LensBoxes<'a> {
+        LensBoxes { boxes: (0..256).map(|_| Default::default()).collect() }
+    }
+
+    pub fn apply(&mut self, step: Step<'a>) {
+        match step {
+            Step::Remove(label) => self.remove(label),
+            Step::Insert(label, focal) => self.insert(label, focal),
+        }
+    }
+
+    pub fn remove(&mut self, label: &str) {
+        self.boxes[hash(label.as_bytes()) as usize].remove(label);
+    }
+
+    pub fn insert(&mut self, label: &'a str, focal: usize) {
+        self.boxes[hash(label.as_bytes()) as usize].insert(label, focal);
+    }
+
+    #[cfg(test)]
+    pub fn get(&self, label: &str) -> Option<usize> {
+        let bx = &self.boxes[hash(label.as_bytes()) as usize];
+        bx.index.get(label).map(|&i| bx.slots[i].unwrap().1)
+    }
+
+    /// Every lens as (box, slot, label, focal length), with boxes and
+    /// slots numbered from 0.
+    pub fn iter(&self) -> impl Iterator<Item=(usize, usize, &'a str, usize)> + '_ {
+        self.boxes
+            .iter()
+            .enumerate()
+            .flat_map(|(b, bx)| {
+                bx.lenses()
+                    .enumerate()
+                    .map(move |(slot, (label, focal))| (b, slot, label, focal))
+            })
+    }
+
+    pub fn focusing_power(&self) -> usize {
+        self.iter()
+            .map(|(b, slot, _, focal)| (b + 1) * (slot + 1) * focal)
+            .sum()
+    }
+}
+
+// The non-empty boxes, as shown in the puzzle.
+impl std::fmt::Display for LensBoxes<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (b, bx) in self.boxes.iter().enumerate() {
+            if bx.index.is_empty() {
+                continue;
             }
-            if !found {
-                bx.push((name, pow));
+            write!(f, "Box {}:", b)?;
+            for (label, focal) in bx.lenses() {
+                write!(f, " [{} {}]", label, focal)?;
             }
+            writeln!(f)?;
         }
+        Ok(())
     }
-    let mut sum = 0;
-    for i in 0..256 {
-        for (j, v) in boxes[i].iter().enumerate() {
-            sum += (i+1) * (j+1) * v.1;
-        }
+}
+
+fn trace(data: &Data) -> String {
+    let mut boxes = LensBoxes::new();
+    let mut result = String::new();
+    for s in data {
+        boxes.apply(parse_step(s));
+        result.push_str(&format!("After \"{}\":\n{}\n", s, boxes));
+    }
+    result
+}
+
+timeit!{
+fn part2(data: &Data) -> usize {
+    let mut boxes = LensBoxes::new();
+    for s in data {
+        boxes.apply(parse_step(s));
     }
-    sum
+    boxes.focusing_power()
 }}
 
 #[test]
@@ -81,6 +170,25 @@ fn test() {
 
     assert_eq!(part1(&data), 1320);
     assert_eq!(part2(&data), 145);
+
+    let mut boxes = LensBoxes::new();
+    for s in &data {
+        boxes.apply(parse_step(s));
+    }
+    assert_eq!(boxes.iter().collect::<Vec<_>>(),
+               vec![(0, 0, "rn", 1), (0, 1, "cm", 2),
+                    (3, 0, "ot", 7), (3, 1, "ab", 5), (3, 2, "pc", 6)]);
+    assert_eq!(boxes.get("ot"), Some(7));
+    assert_eq!(boxes.get("qp"), None);
+    boxes.remove("ot");
+    boxes.remove("ab");
+    boxes.insert("ot", 1);
+    assert_eq!(boxes.iter().filter(|l| l.0 == 3).collect::<Vec<_>>(),
+               vec![(3, 0, "pc", 6), (3, 1, "ot", 1)]);
+
+    let trace = trace(&data);
+    assert!(trace.starts_with("After \"rn=1\":\nBox 0: [rn 1]\n\nAfter \"cm-\":\nBox 0: [rn 1]\n\n"));
+    assert!(trace.ends_with("After \"ot=7\":\nBox 0: [rn 1] [cm 2]\nBox 3: [ot 7] [ab 5] [pc 6]\n\n"));
 }
 
 fn main() -> std::io::Result<()>{
@@ -94,5 +202,9 @@ fn main() -> std::io::Result<()>{
     // Part 2
     println!("{}", part2(&data));
 
+    if std::env::args().any(|arg| arg == "--trace") {
+        print!("{}", trace(&data));
+    }
+
     Ok(())
 }
@@ -1,7 +1,11 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::io;
 
 #[allow(unused)]
 use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
+use adventofcode2023::bits::BitRow;
+use adventofcode2023::viz::{self, Cell, Colour, Control, Grid, Key, Viewer};
+use rayon::prelude::*;
 
 #[derive(Copy, Clone, Eq, PartialEq)]
 enum Sq {
@@ -32,7 +36,7 @@ fn parse_input(input: &str) -> Data {
          .collect()
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 enum Dir {
     Up,
     Down,
@@ -40,6 +44,8 @@ enum Dir {
     Right
 }
 
+type Beam = ((usize, usize), Dir);
+
 fn next_pos((x, y): (usize, usize), width: usize, height: usize, dir: Dir) -> Option<(usize, usize)> {
     match dir {
         Dir::Up => if y > 0 { Some((x, y-1)) } else { None },
@@ -49,67 +55,431 @@ fn next_pos((x, y): (usize, usize), width: usize, height: usize, dir: Dir) -> Op
     }
 }
 
+// The directions a beam leaves a square in.
+fn out_dirs(dir: Dir, sq: Sq) -> Vec<Dir> {
+    use Dir::*;
+    use Sq::*;
+    match (dir, sq) {
+        (_, Empty) => vec![dir],
+        (Right|Left, HorizSplit) => vec![dir],
+        (Up|Down, VertSplit) => vec![dir],
+        (Right|Left, VertSplit) => vec![Up, Down],
+        (Down|Up, HorizSplit) => vec![Left, Right],
+        (Right, Slash) => vec![Up],
+        (Left, Slash) => vec![Down],
+        (Up, Slash) => vec![Right],
+        (Down, Slash) => vec![Left],
+        (Right, BSlash) => vec![Down],
+        (Left, BSlash) => vec![Up],
+        (Up, BSlash) => vec![Left],
+        (Down, BSlash) => vec![Right],
+    }
+}
+
 fn count_energy(data: &Data, pos: (usize, usize), dir: Dir) -> usize {
-    let height = data.len();
-    let width = data[0].len();
     let mut visited = HashSet::new();
     visited.insert((pos, dir));
     let mut beams = vec![(pos, dir)];
     while !beams.is_empty() {
-        // List of possible next positions
-        let mut new_beams = vec![];
-        for ((x, y), dir) in beams {
-            use Dir::*;
-            use Sq::*;
-            let dirs = match (dir, data[y][x]) {
-                (_, Empty) => vec![dir],
-                (Right|Left, HorizSplit) => vec![dir],
-                (Up|Down, VertSplit) => vec![dir],
-                (Right|Left, VertSplit) => vec![Up, Down],
-                (Down|Up, HorizSplit) => vec![Left, Right],
-                (Right, Slash) => vec![Up],
-                (Left, Slash) => vec![Down],
-                (Up, Slash) => vec![Right],
-                (Down, Slash) => vec![Left],
-                (Right, BSlash) => vec![Down],
-                (Left, BSlash) => vec![Up],
-                (Up, BSlash) => vec![Left],
-                (Down, BSlash) => vec![Right],
-            };
-            for d in dirs {
-                if let Some(pos) = next_pos((x, y), width, height, d) {
-                    if visited.insert((pos, d)) {
-                        new_beams.push((pos, d));
-                    }
+        beams = step_beams(data, beams, &mut visited);
+    }
+    visited.into_iter().map(|(pos, _)| pos).collect::<HashSet<(usize, usize)>>().len()
+}
+
+// Move each beam on by one square, returning the ones which haven't been
+// seen before.
+fn step_beams(data: &Data, beams: Vec<Beam>, visited: &mut HashSet<Beam>) -> Vec<Beam> {
+    let height = data.len();
+    let width = data[0].len();
+    // List of possible next positions
+    let mut new_beams = vec![];
+    for ((x, y), dir) in beams {
+        for d in out_dirs(dir, data[y][x]) {
+            if let Some(pos) = next_pos((x, y), width, height, d) {
+                if visited.insert((pos, d)) {
+                    new_beams.push((pos, d));
                 }
             }
         }
-        beams = new_beams;
     }
-    visited.into_iter().map(|(pos, _)| pos).collect::<HashSet<(usize, usize)>>().len()
+    new_beams
 }
 
 timeit!{
 fn part1(data: &Data) -> usize {
     count_energy(data, (0, 0), Dir::Right)
 }}
-timeit!{
-fn part2(data: &Data) -> usize {
+// Every entry point around the edge, with the direction it points in.
+fn entry_points(data: &Data) -> Vec<((usize, usize), Dir)> {
     let height = data.len();
     let width = data[0].len();
 
-    let mut result = 0;
+    let mut result = Vec::new();
     for y in 0..height {
-        result = result.max(count_energy(data, (0, y), Dir::Right));
-        result = result.max(count_energy(data, (width-1, y), Dir::Left));
+        result.push(((0, y), Dir::Right));
+        result.push(((width-1, y), Dir::Left));
     }
     for x in 0..width {
-        result = result.max(count_energy(data, (x, 0), Dir::Down));
-        result = result.max(count_energy(data, (x, height-1), Dir::Up));
+        result.push(((x, 0), Dir::Down));
+        result.push(((x, height-1), Dir::Up));
     }
     result
+}
+
+/// A straight run of beam, from where it enters a square until it's turned
+/// or split by a mirror or splitter, or leaves the grid.
+struct Segment {
+    cells: BitRow,
+    next: Vec<usize>,
+}
+
+/// The graph of beam segments reachable from every entry point, with the
+/// energised cells for each strongly connected component precomputed.
+struct BeamGraph {
+    index: HashMap<Beam, usize>,
+    // The component each segment is in
+    component: Vec<usize>,
+    // All the cells energised from anywhere in each component
+    energised: Vec<BitRow>,
+}
+
+impl BeamGraph {
+    pub fn new(data: &Data) -> BeamGraph {
+        let height = data.len();
+        let width = data[0].len();
+
+        let mut index = HashMap::new();
+        let mut todo = Vec::new();
+        let mut get_id = |beam: Beam, todo: &mut Vec<Beam>| -> usize {
+            let next_id = index.len();
+            *index.entry(beam)
+                  .or_insert_with(|| {
+                      todo.push(beam);
+                      next_id
+                  })
+        };
+        for beam in entry_points(data) {
+            get_id(beam, &mut todo);
+        }
+        // Segments are numbered in the order they're found, but traced
+        // from the worklist, so remember where each one goes.
+        let mut traced = Vec::new();
+        while let Some(beam) = todo.pop() {
+            let id = get_id(beam, &mut todo);
+            let (cells, next_beams) = Self::trace(data, beam);
+            let next = next_beams.into_iter()
+                .map(|b| get_id(b, &mut todo))
+                .collect();
+            traced.push((id, Segment { cells, next }));
+        }
+        traced.sort_by_key(|(id, _)| *id);
+        let segments = traced.into_iter()
+            .map(|(_, seg)| seg)
+            .collect::<Vec<_>>();
+
+        let (component, num_components) = Self::components(&segments);
+
+        // Tarjan's algorithm finishes components in reverse topological
+        // order, so every component a beam can move on to has a lower
+        // number and is already done.
+        let mut members = vec![Vec::new(); num_components];
+        for (i, &c) in component.iter().enumerate() {
+            members[c].push(i);
+        }
+        let mut energised: Vec<BitRow> = Vec::with_capacity(num_components);
+        for (c, segs) in members.iter().enumerate() {
+            let mut cells = BitRow::new(width * height);
+            for &i in segs {
+                cells |= &segments[i].cells;
+                for &j in &segments[i].next {
+                    if component[j] != c {
+                        cells |= &energised[component[j]];
+                    }
+                }
+            }
+            energised.push(cells);
+        }
+
+        BeamGraph {
+            index,
+            component,
+            energised,
+        }
+    }
+
+    // Follow a beam until it's turned, returning the cells it passes
+    // through and the beams it turns into.
+    fn trace(data: &Data, (mut pos, dir): Beam) -> (BitRow, Vec<Beam>) {
+        let height = data.len();
+        let width = data[0].len();
+        let mut cells = BitRow::new(width * height);
+        loop {
+            let (x, y) = pos;
+            cells.set(y * width + x, true);
+            let dirs = out_dirs(dir, data[y][x]);
+            if dirs == [dir] {
+                match next_pos(pos, width, height, dir) {
+                    Some(p) => { pos = p; }
+                    None => { return (cells, vec![]); }
+                }
+            } else {
+                let next = dirs.into_iter()
+                    .filter_map(|d| next_pos(pos, width, height, d).map(|p| (p, d)))
+                    .collect();
+                return (cells, next);
+            }
+        }
+    }
+
+    // Tarjan's strongly connected components, iteratively.  Returns the
+    // component for each segment and the number of components.
+    fn components(segments: &[Segment]) -> (Vec<usize>, usize) {
+        let n = segments.len();
+        let mut index = vec![usize::MAX; n];
+        let mut low = vec![0; n];
+        let mut on_stack = vec![false; n];
+        let mut stack = Vec::new();
+        let mut component = vec![usize::MAX; n];
+        let mut counter = 0;
+        let mut num_components = 0;
+
+        for root in 0..n {
+            if index[root] != usize::MAX {
+                continue;
+            }
+            index[root] = counter;
+            low[root] = counter;
+            counter += 1;
+            stack.push(root);
+            on_stack[root] = true;
+            let mut calls = vec![(root, 0)];
+            while let Some(&(v, i)) = calls.last() {
+                if i < segments[v].next.len() {
+                    calls.last_mut().unwrap().1 += 1;
+                    let w = segments[v].next[i];
+                    if index[w] == usize::MAX {
+                        index[w] = counter;
+                        low[w] = counter;
+                        counter += 1;
+                        stack.push(w);
+                        on_stack[w] = true;
+                        calls.push((w, 0));
+                    } else if on_stack[w] {
+                        low[v] = low[v].min(index[w]);
+                    }
+                } else {
+                    calls.pop();
+                    if let Some(&(u, _)) = calls.last() {
+                        low[u] = low[u].min(low[v]);
+                    }
+                    if low[v] == index[v] {
+                        loop {
+                            let w = stack.pop().unwrap();
+                            on_stack[w] = false;
+                            component[w] = num_components;
+                            if w == v {
+                                break;
+                            }
+                        }
+                        num_components += 1;
+                    }
+                }
+            }
+        }
+        (component, num_components)
+    }
+
+    /// The number of cells energised by a beam entering from the edge.
+    pub fn count_energy(&self, beam: Beam) -> usize {
+        let seg = self.index[&beam];
+        self.energised[self.component[seg]].count_ones()
+    }
+}
+
+timeit!{
+fn part2(data: &Data) -> usize {
+    let graph = BeamGraph::new(data);
+    entry_points(data)
+        .par_iter()
+        .map(|&beam| graph.count_energy(beam))
+        .max()
+        .unwrap()
 }}
 
+/// Beam propagation one step at a time, for the visualiser.
+struct Animation {
+    front: Vec<Beam>,
+    visited: HashSet<Beam>,
+    energised: HashSet<(usize, usize)>,
+    steps: usize,
+}
+
+impl Animation {
+    pub fn new(beam: Beam) -> Animation {
+        Animation {
+            front: vec![beam],
+            visited: [beam].into_iter().collect(),
+            energised: [beam.0].into_iter().collect(),
+            steps: 0,
+        }
+    }
+
+    pub fn step(&mut self, data: &Data) {
+        if self.done() {
+            return;
+        }
+        let front = std::mem::take(&mut self.front);
+        self.front = step_beams(data, front, &mut self.visited);
+        self.energised.extend(self.front.iter().map(|&(pos, _)| pos));
+        self.steps += 1;
+    }
+
+    pub fn done(&self) -> bool {
+        self.front.is_empty()
+    }
+}
+
+// The entry point `index` squares along the edge which beams travelling
+// in `dir` enter from.
+fn edge_entry(data: &Data, dir: Dir, index: usize) -> Beam {
+    let height = data.len();
+    let width = data[0].len();
+    match dir {
+        Dir::Right => ((0, index), dir),
+        Dir::Left => ((width-1, index), dir),
+        Dir::Down => ((index, 0), dir),
+        Dir::Up => ((index, height-1), dir),
+    }
+}
+
+fn sq_char(sq: Sq) -> char {
+    match sq {
+        Sq::Empty => '.',
+        Sq::HorizSplit => '-',
+        Sq::VertSplit => '|',
+        Sq::Slash => '/',
+        Sq::BSlash => '\\',
+    }
+}
+
+fn dir_char(dir: Dir) -> char {
+    match dir {
+        Dir::Up => '^',
+        Dir::Down => 'v',
+        Dir::Left => '<',
+        Dir::Right => '>',
+    }
+}
+
+/// The grid with the beam fronts as arrows, and optionally the energised
+/// squares highlighted.
+struct AnimationView<'a> {
+    data: &'a Data,
+    anim: &'a Animation,
+    fronts: HashMap<(usize, usize), Dir>,
+    highlight: bool,
+}
+
+impl<'a> AnimationView<'a> {
+    pub fn new(data: &'a Data, anim: &'a Animation, highlight: bool) -> AnimationView<'a> {
+        let fronts = anim.front.iter().copied().collect();
+        AnimationView { data, anim, fronts, highlight }
+    }
+}
+
+impl Grid for AnimationView<'_> {
+    fn width(&self) -> usize {
+        self.data[0].len()
+    }
+    fn height(&self) -> usize {
+        self.data.len()
+    }
+    fn cell(&self, x: usize, y: usize) -> Cell {
+        let cell = match self.fronts.get(&(x, y)) {
+            Some(&dir) => Cell::from(dir_char(dir)).fg(Colour::YELLOW),
+            None => sq_char(self.data[y][x]).into(),
+        };
+        if self.highlight && self.anim.energised.contains(&(x, y)) {
+            cell.bg(Colour::BLUE)
+        } else {
+            cell
+        }
+    }
+}
+
+const KEYS: &str = "e: next edge  left/right: move entry  h: highlight  r: restart";
+
+/// Animate the beams from an entry point until they stop spreading.  When
+/// someone's watching, the entry point can be moved along the edges and
+/// the animation restarts from there.
+fn visualise(data: &Data, viewer: &mut dyn Viewer) -> io::Result<()> {
+    let height = data.len();
+    let width = data[0].len();
+    let edges = [Dir::Right, Dir::Down, Dir::Left, Dir::Up];
+    let edge_len = |dir| match dir {
+        Dir::Left | Dir::Right => height,
+        Dir::Up | Dir::Down => width,
+    };
+
+    let mut edge = 0;
+    let mut index = 0;
+    let mut highlight = true;
+    let mut anim = Animation::new(edge_entry(data, edges[edge], index));
+    loop {
+        let ((x, y), dir) = edge_entry(data, edges[edge], index);
+        let mut status = format!("Entry ({}, {}) {:?}  step {}  energised {}{}",
+                                 x, y, dir, anim.steps, anim.energised.len(),
+                                 if anim.done() { "  (done)" } else { "" });
+        if viewer.interactive() {
+            status.push_str(&format!("  [{}]", KEYS));
+        }
+        let view = AnimationView::new(data, &anim, highlight);
+        let mut restart = false;
+        match viewer.show_grid(&view, &status)? {
+            Control::Quit => return Ok(()),
+            Control::Continue => {
+                if anim.done() && !viewer.interactive() {
+                    return Ok(());
+                }
+                anim.step(data);
+            }
+            Control::Key(Key::Char('e')) => {
+                edge = (edge + 1) % edges.len();
+                index = 0;
+                restart = true;
+            }
+            Control::Key(Key::Left) => {
+                index = (index + edge_len(edges[edge]) - 1) % edge_len(edges[edge]);
+                restart = true;
+            }
+            Control::Key(Key::Right) => {
+                index = (index + 1) % edge_len(edges[edge]);
+                restart = true;
+            }
+            Control::Key(Key::Char('h')) => { highlight = !highlight; }
+            Control::Key(Key::Char('r')) => { restart = true; }
+            Control::Key(_) => {}
+        }
+        if restart {
+            anim = Animation::new(edge_entry(data, edges[edge], index));
+        }
+    }
+}
+
+// Trace every entry point separately, for checking against the graph.
+#[cfg(test)]
+mod reference {
+    use super::*;
+
+    pub fn part2_bfs(data: &Data) -> usize {
+        entry_points(data)
+            .par_iter()
+            .map(|&(pos, dir)| count_energy(data, pos, dir))
+            .max()
+            .unwrap()
+    }
+}
+
 #[test]
 fn test() {
     let tests = r#".|...\....
@@ -126,6 +496,74 @@ fn test() {
 
     assert_eq!(part1(&data), 46);
     assert_eq!(part2(&data), 51);
+    assert_eq!(reference::part2_bfs(&data), 51);
+
+    let graph = BeamGraph::new(&data);
+    for (pos, dir) in entry_points(&data) {
+        assert_eq!(graph.count_energy((pos, dir)), count_energy(&data, pos, dir));
+    }
+
+    let mut anim = Animation::new(edge_entry(&data, Dir::Right, 0));
+    while !anim.done() {
+        anim.step(&data);
+    }
+    assert_eq!(anim.energised.len(), 46);
+    let frame = viz::Frame::capture(&AnimationView::new(&data, &anim, true));
+    assert_eq!(frame.to_text().lines().next(), Some(".|...\\...."));
+    assert_eq!(frame.get(0, 0).bg, Some(Colour::BLUE));
+    assert_eq!(frame.get(9, 9).bg, None);
+    let plain = viz::Frame::capture(&AnimationView::new(&data, &anim, false));
+    assert_eq!(plain.get(0, 0).bg, None);
+    assert_eq!(plain.to_text(), frame.to_text());
+
+    // Steering the animation from the keyboard
+    let script = [
+        Control::Continue,
+        Control::Key(Key::Char('e')),
+        Control::Key(Key::Left),
+        Control::Key(Key::Char('h')),
+        Control::Continue,
+        Control::Key(Key::Char('r')),
+    ];
+    let mut viewer = Scripted { script: script.into_iter().collect(), shown: Vec::new() };
+    visualise(&data, &mut viewer).unwrap();
+    let shown = viewer.shown.iter().map(|(status, frame)| {
+        (status.split("  [").next().unwrap().to_string(), frame.get(9, 0).bg.is_some())
+    }).collect::<Vec<_>>();
+    assert_eq!(shown, [
+        ("Entry (0, 0) Right  step 0  energised 1".to_string(), false),
+        ("Entry (0, 0) Right  step 1  energised 2".to_string(), false),
+        ("Entry (0, 0) Down  step 0  energised 1".to_string(), false),
+        ("Entry (9, 0) Down  step 0  energised 1".to_string(), true),
+        ("Entry (9, 0) Down  step 0  energised 1".to_string(), false),
+        ("Entry (9, 0) Down  step 1  energised 2".to_string(), false),
+        ("Entry (9, 0) Down  step 0  energised 1".to_string(), false),
+    ]);
+
+    // A text dump just plays the first beam through.
+    let mut dump = Vec::new();
+    visualise(&data, &mut viz::TextDump::new(&mut dump)).unwrap();
+    let dump = String::from_utf8(dump).unwrap();
+    assert!(dump.lines().any(|l| l.ends_with("energised 46  (done)")));
+}
+
+// A pretend viewer which presses keys from a script, then quits.
+#[cfg(test)]
+struct Scripted {
+    script: std::collections::VecDeque<Control>,
+    shown: Vec<(String, viz::Frame)>,
+}
+
+#[cfg(test)]
+impl Viewer for Scripted {
+    fn show(&mut self, frame: &viz::Frame, status: &str) -> io::Result<Control> {
+        self.shown.push((status.to_string(), frame.clone()));
+        Ok(self.script.pop_front().unwrap_or(Control::Quit))
+    }
+
+    fn interactive(&self) -> bool {
+        true
+    }
 }
 
 fn main() -> std::io::Result<()>{
@@ -139,5 +577,9 @@ fn main() -> std::io::Result<()>{
     // Part 2
     println!("{}", part2(&data));
 
+    if let Some(mut viewer) = viz::viewer_from_args()? {
+        visualise(&data, viewer.as_mut())?;
+    }
+
     Ok(())
 }
@@ -1,7 +1,9 @@
+use std::cmp::Reverse;
 use std::collections::{BinaryHeap, HashMap};
 
 #[allow(unused)]
 use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
+use adventofcode2023::viz::{Cell, Colour, Frame, Grid};
 
 type Data = Vec<Vec<u8>>;
 fn parse_input(input: &str) -> Data {
@@ -10,166 +12,281 @@ fn parse_input(input: &str) -> Data {
         .collect()
 }
 
+type Pos = (usize, usize);
+
+// Unit moves, orthogonal ones first.  Opposite directions are 4 apart.
+const MOVES: [(isize, isize); 8] = [
+    (1, 0), (0, 1), (-1, 0), (0, -1),
+    (1, 1), (-1, 1), (-1, -1), (1, -1),
+];
+
+fn opposite(dir: usize) -> usize {
+    if dir < 4 {
+        (dir + 2) % 4
+    } else {
+        4 + (dir - 4 + 2) % 4
+    }
+}
+
+/// The rules a crucible moves by.
+#[derive(Clone, Debug)]
+struct Rules {
+    // Blocks it must move in a straight line before turning or stopping
+    min_run: usize,
+    // Blocks it can move in a straight line before it must turn
+    max_run: usize,
+    // Whether it can turn straight back the way it came
+    reverse: bool,
+    // Whether it can move diagonally as well
+    diagonal: bool,
+}
+
+impl Rules {
+    pub fn crucible() -> Rules {
+        Rules { min_run: 1, max_run: 3, reverse: false, diagonal: false }
+    }
+
+    pub fn ultra_crucible() -> Rules {
+        Rules { min_run: 4, max_run: 10, reverse: false, diagonal: false }
+    }
+
+    fn num_dirs(&self) -> usize {
+        if self.diagonal { 8 } else { 4 }
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Debug)]
-enum Dir {
-    Up,
-    Down,
-    Left,
-    Right
+struct State {
+    pos: Pos,
+    // Index into MOVES of the last move, or None at the start
+    dir: Option<usize>,
+    // Number of blocks moved in that direction
+    run: usize,
 }
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy, Ord, Debug)]
-struct CellState {
+/// The cheapest route found, including the starting block.
+#[derive(Clone, Debug)]
+struct Route {
     cost: usize,
-    lastdir: Dir,
-    x: usize,
-    y: usize,
+    path: Vec<Pos>,
 }
 
-impl PartialOrd for CellState {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        // Reverse compare on cost, so that lowest cost comes out first from
-        // the BinaryHeap.
-        match other.cost.partial_cmp(&self.cost) {
-            Some(core::cmp::Ordering::Equal) => {}
-            ord => return ord,
-        }
-        match self.lastdir.partial_cmp(&other.lastdir) {
-            Some(core::cmp::Ordering::Equal) => {}
-            ord => return ord,
+/// A city map with a cost for entering each block, or None where the
+/// crucible can't go.
+struct City {
+    width: usize,
+    height: usize,
+    costs: Vec<Option<usize>>,
+}
+
+impl City {
+    pub fn new(data: &Data) -> City {
+        City {
+            width: data[0].len(),
+            height: data.len(),
+            costs: data.iter()
+                       .flat_map(|row| row.iter().map(|&c| Some(c as usize)))
+                       .collect(),
         }
-        match self.x.partial_cmp(&other.x) {
-            Some(core::cmp::Ordering::Equal) => {}
-            ord => return ord,
+    }
+
+    /// Change the cost of entering a block, or with None stop the
+    /// crucible going there at all.
+    pub fn set_cost(&mut self, (x, y): Pos, cost: Option<usize>) {
+        assert!(x < self.width && y < self.height, "({}, {}) is outside the city", x, y);
+        self.costs[y * self.width + x] = cost;
+    }
+
+    fn cost(&self, (x, y): Pos) -> Option<usize> {
+        self.costs[y * self.width + x]
+    }
+
+    fn step(&self, (x, y): Pos, dir: usize) -> Option<Pos> {
+        let (dx, dy) = MOVES[dir];
+        let x = x.checked_add_signed(dx)?;
+        let y = y.checked_add_signed(dy)?;
+        if x < self.width && y < self.height {
+            Some((x, y))
+        } else {
+            None
         }
-        self.y.partial_cmp(&other.y)
     }
-}
 
-fn nextmoves(data: &Data, state: &CellState, min_moves: usize, max_moves: usize) -> Vec<CellState> {
-    let mut newstates = Vec::new();
-    let width = data[0].len();
-    let height = data.len();
-    match state.lastdir {
-        Dir::Up | Dir::Down => {
-            // do left/right moves
-            let mut cost = state.cost;
-            for i in 1..=max_moves {
-                if i > state.x {
-                    break;
-                }
-                let x = state.x - i;
-                cost += data[state.y][x] as usize;
-                if i >= min_moves {
-                    newstates.push(CellState { cost, lastdir: Dir::Left, x, y: state.y });
+    // The states reachable in one move, with the cost of getting there.
+    // After a turn the crucible always goes at least `min_run` blocks, so
+    // that's taken as a single move.
+    fn next_states(&self, rules: &Rules, state: &State) -> Vec<(State, usize)> {
+        let mut result = Vec::new();
+        for dir in 0..rules.num_dirs() {
+            let (run, blocks) = match state.dir {
+                None => (rules.min_run, rules.min_run),
+                Some(d) if d == dir => {
+                    if state.run >= rules.max_run {
+                        continue;
+                    }
+                    (state.run + 1, 1)
                 }
-            }
-
-            cost = state.cost;
-            for i in 1..=max_moves {
-                let x = state.x + i;
-                if x >= width {
-                    break;
+                Some(d) => {
+                    if !rules.reverse && dir == opposite(d) {
+                        continue;
+                    }
+                    (rules.min_run, rules.min_run)
                 }
-                cost += data[state.y][x] as usize;
-                if i >= min_moves {
-                    newstates.push(CellState { cost, lastdir: Dir::Right, x, y: state.y });
+            };
+            let mut pos = state.pos;
+            let mut cost = 0;
+            let reached = (0..blocks).all(|_| {
+                match self.step(pos, dir).and_then(|p| Some((p, self.cost(p)?))) {
+                    Some((p, c)) => {
+                        pos = p;
+                        cost += c;
+                        true
+                    }
+                    None => false,
                 }
+            });
+            if reached {
+                result.push((State { pos, dir: Some(dir), run }, cost));
             }
         }
-        Dir::Left | Dir::Right => {
-            // do up/down moves
-            let mut cost = state.cost;
+        result
+    }
 
-            for i in 1..=max_moves {
-                if i > state.y {
-                    break;
-                }
-                let y = state.y - i;
-                cost += data[y][state.x] as usize;
-                if i >= min_moves {
-                    newstates.push(CellState { cost, lastdir: Dir::Up, x: state.x, y });
-                }
-            }
+    // A dense numbering of the states, with a spare direction for the
+    // start.
+    fn num_states(&self, rules: &Rules) -> usize {
+        self.width * self.height * (rules.num_dirs() + 1) * (rules.max_run + 1)
+    }
 
-            cost = state.cost;
-            for i in 1..=max_moves {
-                let y = state.y + i;
-                if y >= height {
-                    break;
-                }
-                cost += data[y][state.x] as usize;
-                if i >= min_moves {
-                    newstates.push(CellState { cost, lastdir: Dir::Down, x: state.x, y });
-                }
-            }
+    fn state_index(&self, rules: &Rules, state: &State) -> usize {
+        let dir = state.dir.unwrap_or(rules.num_dirs());
+        let (x, y) = state.pos;
+        ((y * self.width + x) * (rules.num_dirs() + 1) + dir) * (rules.max_run + 1) + state.run
+    }
+
+    fn state_at(&self, rules: &Rules, index: usize) -> State {
+        let run = index % (rules.max_run + 1);
+        let index = index / (rules.max_run + 1);
+        let dir = index % (rules.num_dirs() + 1);
+        let index = index / (rules.num_dirs() + 1);
+        State {
+            pos: (index % self.width, index / self.width),
+            dir: if dir < rules.num_dirs() { Some(dir) } else { None },
+            run,
         }
     }
-    newstates
-}
 
-fn solve(data: &Data, min_move: usize, max_move: usize) -> usize {
-    let mut seen = HashMap::new();
-    let mut options = BinaryHeap::new();
-    let mut best_cost = usize::MAX;
+    /// Find the cheapest route from any of the starts to any of the goals.
+    pub fn find_route(&self, rules: &Rules, starts: &[Pos], goals: &[Pos]) -> Option<Route> {
+        // Cheapest cost found for each state, and the state it came from
+        let mut best = vec![usize::MAX; self.num_states(rules)];
+        let mut prev = vec![None; self.num_states(rules)];
+        let mut options = BinaryHeap::new();
 
-    let height = data.len();
-    let width = data[0].len();
-    let target = (width-1, height-1);
-
-    // Initial starting points - we could have entered the top-left corner 
-    // from two directions.
-    options.push(CellState {
-        cost: 0,
-        lastdir: Dir::Right,
-        x: 0,
-        y: 0,
-    });
-    options.push(CellState {
-        cost: 0,
-        lastdir: Dir::Down,
-        x: 0,
-        y: 0,
-    });
-
-    while let Some(state) = options.pop() {
-        if state.cost > best_cost {
-            break;
+        for &pos in starts {
+            let index = self.state_index(rules, &State { pos, dir: None, run: 0 });
+            best[index] = 0;
+            options.push(Reverse((0, index)));
         }
 
-        for newstate in nextmoves(data, &state, min_move, max_move) {
-            let seenkey = (newstate.x, newstate.y, newstate.lastdir);
-            let prevseen = seen.get_mut(&seenkey);
-            if match prevseen {
-                Some(cost) => {
-                    // Only process if the new one is lower cost
-                    *cost > newstate.cost
+        while let Some(Reverse((cost, index))) = options.pop() {
+            if best[index] < cost {
+                continue;
+            }
+            let state = self.state_at(rules, index);
+            if goals.contains(&state.pos) && state.dir.is_some() && state.run >= rules.min_run {
+                // Walk back through the predecessors for the path, filling
+                // in the blocks skipped over after each turn.
+                let mut path = vec![state.pos];
+                let mut cur = index;
+                while let Some(p) = prev[cur] {
+                    let (from, to) = (self.state_at(rules, p), self.state_at(rules, cur));
+                    let back = opposite(to.dir.unwrap());
+                    let mut pos = to.pos;
+                    while pos != from.pos {
+                        pos = self.step(pos, back).unwrap();
+                        path.push(pos);
+                    }
+                    cur = p;
                 }
-                // Do want to process it if we haven't seen it before
-                None => true,
-            } {
-                if (newstate.x, newstate.y) == target {
-                    best_cost = best_cost.min(newstate.cost);
+                path.reverse();
+                return Some(Route { cost, path });
+            }
+            for (newstate, step_cost) in self.next_states(rules, &state) {
+                let newcost = cost + step_cost;
+                let newindex = self.state_index(rules, &newstate);
+                if newcost < best[newindex] {
+                    best[newindex] = newcost;
+                    prev[newindex] = Some(index);
+                    options.push(Reverse((newcost, newindex)));
                 }
-                options.push(newstate.clone());
-                seen.insert((newstate.x, newstate.y, newstate.lastdir), newstate.cost);
             }
         }
-        //print!("\r{}", seen.len());
+        None
     }
+}
 
-    best_cost
+fn solve(data: &Data, rules: &Rules) -> Route {
+    let height = data.len();
+    let width = data[0].len();
+    City::new(data)
+        .find_route(rules, &[(0, 0)], &[(width-1, height-1)])
+        .expect("No route")
 }
 
 timeit!{
 fn part1(data: &Data) -> usize {
-    solve(data, 1, 3)
+    solve(data, &Rules::crucible()).cost
 }}
 timeit!{
 fn part2(data: &Data) -> usize {
-    solve(data, 4, 10)
+    solve(data, &Rules::ultra_crucible()).cost
 }}
 
+/// The heat loss map with a route drawn over it, in the style of the
+/// puzzle description.
+struct RouteView<'a> {
+    data: &'a Data,
+    // The direction moved to enter each block on the route
+    moves: HashMap<Pos, (isize, isize)>,
+}
+
+impl<'a> RouteView<'a> {
+    pub fn new(data: &'a Data, route: &Route) -> RouteView<'a> {
+        let moves = route.path
+            .windows(2)
+            .map(|w| (w[1], (w[1].0 as isize - w[0].0 as isize,
+                             w[1].1 as isize - w[0].1 as isize)))
+            .collect();
+        RouteView { data, moves }
+    }
+}
+
+impl Grid for RouteView<'_> {
+    fn width(&self) -> usize {
+        self.data[0].len()
+    }
+    fn height(&self) -> usize {
+        self.data.len()
+    }
+    fn cell(&self, x: usize, y: usize) -> Cell {
+        match self.moves.get(&(x, y)) {
+            Some(&mv) => {
+                let glyph = match mv {
+                    (1, 0) => '>',
+                    (-1, 0) => '<',
+                    (0, 1) => 'v',
+                    (0, -1) => '^',
+                    (1, 1) | (-1, -1) => '\\',
+                    _ => '/',
+                };
+                Cell::from(glyph).fg(Colour::RED)
+            }
+            None => char::from(b'0' + self.data[y][x]).into(),
+        }
+    }
+}
+
 #[test]
 fn test() {
     let tests = r#"2413432311323
@@ -189,6 +306,61 @@ fn test() {
 
     assert_eq!(part1(&data), 102);
     assert_eq!(part2(&data), 94);
+
+    let route = solve(&data, &Rules::crucible());
+    check_route(&data, &route, &Rules::crucible());
+    let drawn = Frame::capture(&RouteView::new(&data, &route)).to_text();
+    assert_eq!(drawn.lines().next(), Some("2>>34^>>>1323"));
+    assert_eq!(drawn.lines().last(), Some("43226746555v>"));
+    check_route(&data, &solve(&data, &Rules::ultra_crucible()), &Rules::ultra_crucible());
+
+    let unfortunate = parse_input(r#"111111111111
+999999999991
+999999999991
+999999999991
+999999999991"#);
+    assert_eq!(part2(&unfortunate), 71);
+
+    // Relaxing the rules can only make things cheaper.
+    let diagonal = Rules { diagonal: true, ..Rules::crucible() };
+    let route = solve(&data, &diagonal);
+    check_route(&data, &route, &diagonal);
+    assert!(route.cost < 102);
+    let reverse = Rules { reverse: true, ..Rules::crucible() };
+    assert!(solve(&data, &reverse).cost <= 102);
+
+    // The cheaper of two goals, with a block that can't be entered.
+    let mut city = City::new(&data);
+    city.set_cost((1, 0), None);
+    let rules = Rules::crucible();
+    let to_corner = |goal| city.find_route(&rules, &[(0, 0)], &[goal]).unwrap();
+    let (right, bottom) = (to_corner((12, 0)), to_corner((0, 12)));
+    assert!(!right.path.contains(&(1, 0)));
+    let route = city.find_route(&rules, &[(0, 0)], &[(12, 0), (0, 12)]).unwrap();
+    assert_eq!(route.cost, right.cost.min(bottom.cost));
+}
+
+#[cfg(test)]
+fn check_route(data: &Data, route: &Route, rules: &Rules) {
+    assert_eq!(route.path.first(), Some(&(0, 0)));
+    assert_eq!(route.cost,
+               route.path[1..].iter().map(|&(x, y)| data[y][x] as usize).sum::<usize>());
+    let moves = route.path
+        .windows(2)
+        .map(|w| (w[1].0 as isize - w[0].0 as isize, w[1].1 as isize - w[0].1 as isize))
+        .collect::<Vec<_>>();
+    let mut run = 0;
+    for (i, mv) in moves.iter().enumerate() {
+        assert!(mv.0.abs() <= 1 && mv.1.abs() <= 1 && *mv != (0, 0));
+        assert!(rules.diagonal || mv.0 == 0 || mv.1 == 0);
+        if i > 0 && moves[i-1] != *mv {
+            assert!(run >= rules.min_run);
+            run = 0;
+        }
+        run += 1;
+        assert!(run <= rules.max_run);
+    }
+    assert!(run >= rules.min_run);
 }
 
 fn main() -> std::io::Result<()>{
@@ -202,5 +374,36 @@ fn main() -> std::io::Result<()>{
     // Part 2
     println!("{}", part2(&data));
 
+    // Show the routes, optionally with some blocks closed off.
+    let mut show_path = false;
+    let mut city = City::new(&data);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--path" => { show_path = true; }
+            "--block" => {
+                let block = args.next().expect("--block needs a position x,y");
+                let (x, y) = block.split_once(',').expect("--block needs a position x,y");
+                city.set_cost((x.parse().expect("Bad x"), y.parse().expect("Bad y")), None);
+            }
+            _ => {
+                eprintln!("Unknown option {}", arg);
+                std::process::exit(1);
+            }
+        }
+    }
+    if show_path {
+        let goal = (data[0].len() - 1, data.len() - 1);
+        for rules in [Rules::crucible(), Rules::ultra_crucible()] {
+            match city.find_route(&rules, &[(0, 0)], &[goal]) {
+                Some(route) => {
+                    println!("{:?}: heat loss {}", rules, route.cost);
+                    print!("{}", Frame::capture(&RouteView::new(&data, &route)).to_ansi());
+                }
+                None => println!("{:?}: no route", rules),
+            }
+        }
+    }
+
     Ok(())
 }
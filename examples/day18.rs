@@ -1,7 +1,9 @@
-use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::Write;
 
 #[allow(unused)]
 use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
+use adventofcode2023::polygon::Polygon;
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum Dir {
@@ -30,287 +32,428 @@ regex_parser!(parse_dig: Dig {
         | dir: Dir, dist: isize, colour: String | Dig { dir, dist, colour }
 });
 
+impl Dir {
+    fn step(self) -> (isize, isize) {
+        match self {
+            Dir::Up => (0, -1),
+            Dir::Down => (0, 1),
+            Dir::Left => (-1, 0),
+            Dir::Right => (1, 0),
+        }
+    }
+}
+
+impl Dig {
+    /// The real instruction hidden in the colour, for part 2.
+    fn decode_colour(&self) -> (Dir, isize) {
+        let dir = match self.colour.as_bytes()[5] {
+            b'3' => Dir::Up,
+            b'1' => Dir::Down,
+            b'2' => Dir::Left,
+            b'0' => Dir::Right,
+            _ => panic!(),
+        };
+        let dist = isize::from_str_radix(&self.colour[0..5], 16).unwrap();
+        (dir, dist)
+    }
+}
+
+fn dig_polygon<I: IntoIterator<Item = (Dir, isize)>>(steps: I) -> Polygon {
+    Polygon::from_steps(
+        steps.into_iter()
+             .map(|(dir, dist)| (dir.step(), dist)))
+}
+
 type Data = Vec<Dig>;
 fn parse_input(input: &str) -> Data {
     parse_lines(input)
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-enum CrossType {
-    None,
-    OnEdge,
-    CrossEdge,
+timeit!{
+fn part1(data: &Data) -> isize {
+    dig_polygon(data.iter().map(|dig| (dig.dir, dig.dist)))
+        .lattice_points()
+}}
+
+timeit!{
+fn part2(data: &Data) -> isize {
+    dig_polygon(data.iter().map(Dig::decode_colour))
+        .lattice_points()
+}}
+
+fn parse_colour(colour: &str) -> (u8, u8, u8) {
+    let rgb = u32::from_str_radix(colour, 16).unwrap();
+    ((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
 }
 
-// Say whether we cr
-fn crosses(pos: (isize, isize), edge: &((isize, isize), (isize, isize))) -> CrossType {
-    if edge.0.0 == edge.1.0 {
-        // Vertical edge
-        if edge.0.0 == pos.0 {
-            let y1 = edge.0.1.min(edge.1.1);
-            let y2 = edge.0.1.max(edge.1.1);
-            if y1 == pos.1 {
-                CrossType::OnEdge
-            } else if y1 <= pos.1 && pos.1 <= y2 {
-                CrossType::CrossEdge
-            } else {
-                CrossType::None
+const FILL_COLOUR: (u8, u8, u8) = (0x60, 0x60, 0x60);
+
+/// Draw the trench as SVG, with each edge in its own colour.  The viewBox
+/// is in dig coordinates, so the viewer does any scaling down.
+fn render_svg(polygon: &Polygon, colours: &[String], max_size: usize, fill: bool) -> String {
+    let vertices = polygon.vertices();
+    let min_x = vertices.iter().map(|v| v.0).min().unwrap();
+    let max_x = vertices.iter().map(|v| v.0).max().unwrap();
+    let min_y = vertices.iter().map(|v| v.1).min().unwrap();
+    let max_y = vertices.iter().map(|v| v.1).max().unwrap();
+    let width = max_x - min_x + 1;
+    let height = max_y - min_y + 1;
+    let scale = max_size as f64 / width.max(height) as f64;
+
+    let mut result = String::new();
+    result.push_str(&format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+             viewBox=\"{} {} {} {}\">\n",
+            (width as f64 * scale).ceil(), (height as f64 * scale).ceil(),
+            min_x as f64 - 0.5, min_y as f64 - 0.5, width, height));
+    if fill {
+        let points = vertices.iter()
+            .map(|(x, y)| format!("{},{}", x, y))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let (r, g, b) = FILL_COLOUR;
+        result.push_str(&format!(
+                "<polygon points=\"{}\" fill=\"#{:02x}{:02x}{:02x}\"/>\n",
+                points, r, g, b));
+    }
+    for ((a, b), colour) in polygon.edges().zip(colours) {
+        result.push_str(&format!(
+                "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#{}\" \
+                 stroke-width=\"2\" stroke-linecap=\"square\" \
+                 vector-effect=\"non-scaling-stroke\"/>\n",
+                a.0, a.1, b.0, b.1, colour));
+    }
+    result.push_str("</svg>\n");
+    result
+}
+
+/// Draw the trench as a binary PPM image no bigger than `max_size` pixels
+/// each way.  At full scale each pixel is one cubic metre, so the number
+/// of non-black pixels with `fill` set is the dug volume.
+fn render_ppm(polygon: &Polygon, colours: &[String], max_size: usize, fill: bool) -> Vec<u8> {
+    let vertices = polygon.vertices();
+    let min_x = vertices.iter().map(|v| v.0).min().unwrap();
+    let max_x = vertices.iter().map(|v| v.0).max().unwrap();
+    let min_y = vertices.iter().map(|v| v.1).min().unwrap();
+    let max_y = vertices.iter().map(|v| v.1).max().unwrap();
+    let extent = (max_x - min_x).max(max_y - min_y) as f64;
+    let scale = if extent + 1.0 <= max_size as f64 {
+        1.0
+    } else {
+        (max_size - 1) as f64 / extent
+    };
+    let to_pixel = |(x, y): (isize, isize)| -> (usize, usize) {
+        (((x - min_x) as f64 * scale).round() as usize,
+         ((y - min_y) as f64 * scale).round() as usize)
+    };
+    let (width, height) = to_pixel((max_x, max_y));
+    let (width, height) = (width + 1, height + 1);
+    let mut pixels = vec![(0u8, 0u8, 0u8); width * height];
+
+    if fill {
+        // Even-odd test against the vertical edges, counting an edge as
+        // covering [top, bottom) so that corners are only counted once.
+        let verticals = polygon.edges()
+            .filter(|(a, b)| a.0 == b.0 && a.1 != b.1)
+            .map(|(a, b)| (a.0 as f64, a.1.min(b.1) as f64, a.1.max(b.1) as f64))
+            .collect::<Vec<_>>();
+        for py in 0..height {
+            let y = min_y as f64 + py as f64 / scale;
+            for px in 0..width {
+                let x = min_x as f64 + px as f64 / scale;
+                let crossings = verticals.iter()
+                    .filter(|&&(ex, top, bottom)| ex > x && top <= y && y < bottom)
+                    .count();
+                if crossings % 2 == 1 {
+                    pixels[py * width + px] = FILL_COLOUR;
+                }
             }
-        } else {
-            CrossType::None
         }
-    } else if edge.0.1 == edge.1.1 {
-        // Horizontal edge
-        if edge.0.1 == pos.1 {
-            let x1 = edge.0.0.min(edge.1.0);
-            let x2 = edge.0.0.max(edge.1.0);
-            if x1 <= pos.0 && pos.0 <= x2 {
-                CrossType::OnEdge
-            } else {
-                CrossType::None
+    }
+
+    for ((a, b), colour) in polygon.edges().zip(colours) {
+        let colour = parse_colour(colour);
+        let (ax, ay) = to_pixel(a);
+        let (bx, by) = to_pixel(b);
+        for py in ay.min(by)..=ay.max(by) {
+            for px in ax.min(bx)..=ax.max(bx) {
+                pixels[py * width + px] = colour;
             }
-        } else {
-            CrossType::None
         }
-    } else {
-        panic!()
     }
+
+    let mut result = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    for (r, g, b) in pixels {
+        result.extend_from_slice(&[r, g, b]);
+    }
+    result
 }
 
-timeit!{
-fn part1(data: &Data) -> usize {
-    let mut pos = (0, 0);
-    let mut edges: Vec<((isize, isize), (isize, isize))> = Vec::new();
+// The original cell-by-cell versions, for checking against the polygon
+// ones.
+#[cfg(test)]
+mod reference {
+    use std::collections::BTreeSet;
 
-    let mut min_x = isize::MAX;
-    let mut min_y = isize::MAX;
-    let mut max_x = isize::MIN;
-    let mut max_y = isize::MIN;
+    use super::*;
 
-    for dig in data {
-        let inc = match dig.dir {
-            Dir::Up => (0, -1),
-            Dir::Down => (0, 1),
-            Dir::Left => (-1, 0),
-            Dir::Right => (1, 0),
-        };
-        let old_pos = pos;
-        pos.0 += dig.dist * inc.0;
-        pos.1 += dig.dist * inc.1;
-        min_x = min_x.min(pos.0);
-        max_x = max_x.max(pos.0);
-        min_y = min_y.min(pos.1);
-        max_y = max_y.max(pos.1);
-        edges.push((old_pos,pos));
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    enum CrossType {
+        None,
+        OnEdge,
+        CrossEdge,
     }
-    assert_eq!(pos, (0, 0));
-
-    let mut dug = 0;
-    for y in min_y..=max_y {
-        let mut inside = false;
-        let mut row = String::new();
-        for x in min_x..=max_x {
-            let mut on_edge = false;
-            for edge in &edges {
-                let cross = crosses((x, y), edge);
-                match cross {
-                    CrossType::None => {
-                    }
-                    CrossType::OnEdge => {
-                        on_edge = true;
-                    }
-                    CrossType::CrossEdge => {
-                        on_edge = true;
-                        inside = !inside;
-                    }
+
+    // Say how a scan along the row through `pos` meets `edge`.
+    fn crosses(pos: (isize, isize), edge: &((isize, isize), (isize, isize))) -> CrossType {
+        if edge.0.0 == edge.1.0 {
+            // Vertical edge
+            if edge.0.0 == pos.0 {
+                let y1 = edge.0.1.min(edge.1.1);
+                let y2 = edge.0.1.max(edge.1.1);
+                if y1 == pos.1 {
+                    CrossType::OnEdge
+                } else if y1 <= pos.1 && pos.1 <= y2 {
+                    CrossType::CrossEdge
+                } else {
+                    CrossType::None
                 }
+            } else {
+                CrossType::None
             }
-            if on_edge || inside {
-                if on_edge {
-                    row.push('x');
+        } else if edge.0.1 == edge.1.1 {
+            // Horizontal edge
+            if edge.0.1 == pos.1 {
+                let x1 = edge.0.0.min(edge.1.0);
+                let x2 = edge.0.0.max(edge.1.0);
+                if x1 <= pos.0 && pos.0 <= x2 {
+                    CrossType::OnEdge
                 } else {
-                    row.push('#');
+                    CrossType::None
                 }
-                dug += 1;
             } else {
-                row.push('.');
+                CrossType::None
             }
+        } else {
+            panic!()
         }
-//        println!("{row}");
     }
-    dug
-}}
 
-#[derive(Eq, Ord, PartialEq, PartialOrd, Debug, Copy, Clone)]
-enum EdgeType {
-    Up,   // At the bottom of the edge
-    Down, // At the top of the edge
-    Cross // Edge goes up and down from here
-}
-timeit!{
-fn part2(data: &Data) -> isize {
-    let mut pos = (0, 0);
-    let mut edges: Vec<((isize, isize), (isize, isize))> = Vec::new();
-
-    let mut min_x = isize::MAX;
-    let mut min_y = isize::MAX;
-    let mut max_x = isize::MIN;
-    let mut max_y = isize::MIN;
-
-    let mut y_values = BTreeSet::new();
-
-    for dig in data {
-        let inc = match dig.colour.as_bytes()[5] {
-            b'3' => (0, -1),
-            b'1' => (0, 1),
-            b'2' => (-1, 0),
-            b'0' => (1, 0),
-            _ => panic!(),
-        };
-        let dist = isize::from_str_radix(&dig.colour[0..5], 16).unwrap();
-        let old_pos = pos;
-        pos.0 += dist * inc.0;
-        pos.1 += dist * inc.1;
-        min_x = min_x.min(pos.0);
-        max_x = max_x.max(pos.0);
-        min_y = min_y.min(pos.1);
-        max_y = max_y.max(pos.1);
-        y_values.insert(pos.1);
-
-        if inc.0 == 0 {
+    // Scan every cell, for checking against the polygon version.
+    pub fn part1_scan(data: &Data) -> usize {
+        let mut pos = (0, 0);
+        let mut edges: Vec<((isize, isize), (isize, isize))> = Vec::new();
+
+        let mut min_x = isize::MAX;
+        let mut min_y = isize::MAX;
+        let mut max_x = isize::MIN;
+        let mut max_y = isize::MIN;
+
+        for dig in data {
+            let inc = dig.dir.step();
+            let old_pos = pos;
+            pos.0 += dig.dist * inc.0;
+            pos.1 += dig.dist * inc.1;
+            min_x = min_x.min(pos.0);
+            max_x = max_x.max(pos.0);
+            min_y = min_y.min(pos.1);
+            max_y = max_y.max(pos.1);
             edges.push((old_pos,pos));
         }
-    }
-    assert_eq!(pos, (0, 0));
-    let y_values = y_values.into_iter().collect::<Vec<_>>();
-
-    let mut dug = 0;
-
-    let mut num_inside_in_row = 0;
-    let mut last_y = 0;
-    for y in y_values.into_iter() {
-        // Add up the rows between the previous and current y we've considered
-        dug += num_inside_in_row * (y - last_y - 1);
-
-        num_inside_in_row = 0;
-        last_y = y;
-
-        let mut row_edges: Vec<_> = edges.iter()
-            .filter(|e| (e.0.1.min(e.1.1) <= y &&
-                         e.0.1.max(e.1.1) >= y))
-            .map(|e| {
-                let ymin = e.0.1.min(e.1.1);
-                let ymax = e.0.1.max(e.1.1);
-                let x = e.0.0;
-                if ymin == y {
-                    (x, EdgeType::Down)
-                } else if ymax == y {
-                    (x, EdgeType::Up)
-                } else {
-                    (x, EdgeType::Cross)
+        assert_eq!(pos, (0, 0));
+
+        let mut dug = 0;
+        for y in min_y..=max_y {
+            let mut inside = false;
+            for x in min_x..=max_x {
+                let mut on_edge = false;
+                for edge in &edges {
+                    let cross = crosses((x, y), edge);
+                    match cross {
+                        CrossType::None => {
+                        }
+                        CrossType::OnEdge => {
+                            on_edge = true;
+                        }
+                        CrossType::CrossEdge => {
+                            on_edge = true;
+                            inside = !inside;
+                        }
+                    }
                 }
-            })
-            .collect();
-        row_edges.sort();
-
-        let mut inside = false;
-        // Some(x value at start of horizontal edge)
-        let mut start_inside = None;
-
-        // State for tracking current row
-        let mut hor_start = None;
-        let mut row_inside_start = None;
-
-        for (x, et) in row_edges {
-            // First work out what will happen for the rows below
-            match et {
-                EdgeType::Up => {
-                    // Ignore, as we're working just below the horizontal line.
+                if on_edge || inside {
+                    dug += 1;
                 }
-                EdgeType::Down |
-                EdgeType::Cross => {
-                    // Crossing from inside 
-                    if inside {
-                        num_inside_in_row += x - start_inside.take().unwrap() + 1;
-                        inside = false;
+            }
+        }
+        dug
+    }
+
+    #[derive(Eq, Ord, PartialEq, PartialOrd, Debug, Copy, Clone)]
+    enum EdgeType {
+        Up,   // At the bottom of the edge
+        Down, // At the top of the edge
+        Cross // Edge goes up and down from here
+    }
+    // Sweep down the rows, for checking against the polygon version.
+    pub fn part2_sweep(data: &Data) -> isize {
+        let mut pos = (0, 0);
+        let mut edges: Vec<((isize, isize), (isize, isize))> = Vec::new();
+
+        let mut min_x = isize::MAX;
+        let mut min_y = isize::MAX;
+        let mut max_x = isize::MIN;
+        let mut max_y = isize::MIN;
+
+        let mut y_values = BTreeSet::new();
+
+        for dig in data {
+            let (dir, dist) = dig.decode_colour();
+            let inc = dir.step();
+            let old_pos = pos;
+            pos.0 += dist * inc.0;
+            pos.1 += dist * inc.1;
+            min_x = min_x.min(pos.0);
+            max_x = max_x.max(pos.0);
+            min_y = min_y.min(pos.1);
+            max_y = max_y.max(pos.1);
+            y_values.insert(pos.1);
+
+            if inc.0 == 0 {
+                edges.push((old_pos,pos));
+            }
+        }
+        assert_eq!(pos, (0, 0));
+        let y_values = y_values.into_iter().collect::<Vec<_>>();
+
+        let mut dug = 0;
+
+        let mut num_inside_in_row = 0;
+        let mut last_y = 0;
+        for y in y_values.into_iter() {
+            // Add up the rows between the previous and current y we've considered
+            dug += num_inside_in_row * (y - last_y - 1);
+
+            num_inside_in_row = 0;
+            last_y = y;
+
+            let mut row_edges: Vec<_> = edges.iter()
+                .filter(|e| (e.0.1.min(e.1.1) <= y &&
+                             e.0.1.max(e.1.1) >= y))
+                .map(|e| {
+                    let ymin = e.0.1.min(e.1.1);
+                    let ymax = e.0.1.max(e.1.1);
+                    let x = e.0.0;
+                    if ymin == y {
+                        (x, EdgeType::Down)
+                    } else if ymax == y {
+                        (x, EdgeType::Up)
                     } else {
-                        assert!(start_inside.is_none());
-                        start_inside = Some(x);
-                        inside = true;
+                        (x, EdgeType::Cross)
+                    }
+                })
+                .collect();
+            row_edges.sort();
+
+            let mut inside = false;
+            // Some(x value at start of horizontal edge)
+            let mut start_inside = None;
+
+            // State for tracking current row
+            let mut hor_start = None;
+            let mut row_inside_start = None;
+
+            for (x, et) in row_edges {
+                // First work out what will happen for the rows below
+                match et {
+                    EdgeType::Up => {
+                        // Ignore, as we're working just below the horizontal line.
+                    }
+                    EdgeType::Down |
+                    EdgeType::Cross => {
+                        // Crossing from inside 
+                        if inside {
+                            num_inside_in_row += x - start_inside.take().unwrap() + 1;
+                            inside = false;
+                        } else {
+                            assert!(start_inside.is_none());
+                            start_inside = Some(x);
+                            inside = true;
+                        }
                     }
                 }
-            }
 
-            // Now handle the current row
-            match (et, hor_start, row_inside_start) {
-                (EdgeType::Cross, None, None) => {
-                    // Cross from outside to inside
-                    row_inside_start = Some(x);
-                }
-                (EdgeType::Cross, None, Some(start)) => {
-                    // Cross from inside to outside
-                    dug += x - start + 1;
-                    row_inside_start = None;
-                }
-                (EdgeType::Cross, Some(_hstart), _) => {
-                    // Can't have a crossing inside a horizontal run.
-                    panic!();
-                }
-                (EdgeType::Up, None, None) => {
-                    // Start of horizontal run, and outside
-                    hor_start = Some(x);
-                }
-                (EdgeType::Down, None, None) => {
-                    // Start of horizontal run, crossing to inside
-                    hor_start = Some(x);
-                    row_inside_start = Some(x);
-                }
-                (EdgeType::Up, Some(hstart), None) => {
-                    // end of a horizontal run, otherwise outside
-                    dug += x - hstart + 1;
-                    hor_start = None;
-                }
-                (EdgeType::Up, None, Some(start)) => {
-                    // Start of horizontal run, and we're inside
-                    // Add previous bit of inside
-                    dug += x - start;
-                    hor_start = Some(x);
-                }
-                (EdgeType::Down, None, Some(start)) => {
-                    // Start of horizontal run, and we were inside
-                    // Add previous bit of inside
-                    dug += x - start;
-                    row_inside_start = None;
-                    hor_start = Some(x);
-                }
-                (EdgeType::Up, Some(hstart), Some(_start)) => {
-                    // End of horizontal edge, staying inside
-                    dug += x - hstart + 1;
-                    hor_start = None;
-                    // Start new inside section, as we've alreayd covered
-                    // up to here.
-                    row_inside_start = Some(x+1);
-                }
-                (EdgeType::Down, Some(hstart), None) => {
-                    // End of horizontal run, were outside but now inside.
-                    dug += x - hstart + 1;
-                    hor_start = None;
-                    row_inside_start = Some(x+1);
-                }
-                (EdgeType::Down, Some(hstart), Some(_start)) => {
-                    // End of horizontal run, were inside but now outside
-                    dug += x - hstart + 1;
-                    hor_start = None;
-                    row_inside_start = None;
+                // Now handle the current row
+                match (et, hor_start, row_inside_start) {
+                    (EdgeType::Cross, None, None) => {
+                        // Cross from outside to inside
+                        row_inside_start = Some(x);
+                    }
+                    (EdgeType::Cross, None, Some(start)) => {
+                        // Cross from inside to outside
+                        dug += x - start + 1;
+                        row_inside_start = None;
+                    }
+                    (EdgeType::Cross, Some(_hstart), _) => {
+                        // Can't have a crossing inside a horizontal run.
+                        panic!();
+                    }
+                    (EdgeType::Up, None, None) => {
+                        // Start of horizontal run, and outside
+                        hor_start = Some(x);
+                    }
+                    (EdgeType::Down, None, None) => {
+                        // Start of horizontal run, crossing to inside
+                        hor_start = Some(x);
+                        row_inside_start = Some(x);
+                    }
+                    (EdgeType::Up, Some(hstart), None) => {
+                        // end of a horizontal run, otherwise outside
+                        dug += x - hstart + 1;
+                        hor_start = None;
+                    }
+                    (EdgeType::Up, None, Some(start)) => {
+                        // Start of horizontal run, and we're inside
+                        // Add previous bit of inside
+                        dug += x - start;
+                        hor_start = Some(x);
+                    }
+                    (EdgeType::Down, None, Some(start)) => {
+                        // Start of horizontal run, and we were inside
+                        // Add previous bit of inside
+                        dug += x - start;
+                        row_inside_start = None;
+                        hor_start = Some(x);
+                    }
+                    (EdgeType::Up, Some(hstart), Some(_start)) => {
+                        // End of horizontal edge, staying inside
+                        dug += x - hstart + 1;
+                        hor_start = None;
+                        // Start new inside section, as we've alreayd covered
+                        // up to here.
+                        row_inside_start = Some(x+1);
+                    }
+                    (EdgeType::Down, Some(hstart), None) => {
+                        // End of horizontal run, were outside but now inside.
+                        dug += x - hstart + 1;
+                        hor_start = None;
+                        row_inside_start = Some(x+1);
+                    }
+                    (EdgeType::Down, Some(hstart), Some(_start)) => {
+                        // End of horizontal run, were inside but now outside
+                        dug += x - hstart + 1;
+                        hor_start = None;
+                        row_inside_start = None;
+                    }
                 }
             }
+            assert!(start_inside.is_none());
+            assert!(row_inside_start.is_none());
+            assert!(!inside);
         }
-        assert!(start_inside.is_none());
-        assert!(row_inside_start.is_none());
-        assert!(!inside);
+        dug
     }
-    dug
-}}
+}
 
 #[test]
 fn test() {
@@ -331,6 +474,13 @@ U 2 (#7a21e3)"#;
     let data = parse_input(&tests);
 
     assert_eq!(part1(&data), 62);
+    assert_eq!(reference::part1_scan(&data), 62);
+
+    let polygon = dig_polygon(data.iter().map(|dig| (dig.dir, dig.dist)));
+    assert_eq!(polygon.double_area(), 2 * 42);
+    assert_eq!(polygon.perimeter(), 38);
+    assert_eq!(polygon.boundary_points(), 38);
+    assert_eq!(polygon.interior_points(), 24);
 
     let test2_sq = r#"R 0 (#000020)
 R 0 (#000021)
@@ -338,8 +488,33 @@ R 0 (#000022)
 R 0 (#000023)"#;
     let test2_sq_data = parse_input(&test2_sq);
     assert_eq!(part2(&test2_sq_data), 9);
+    assert_eq!(reference::part2_sweep(&test2_sq_data), 9);
 
     assert_eq!(part2(&data), 952408144115);
+    assert_eq!(reference::part2_sweep(&data), 952408144115);
+
+    let colours = data.iter().map(|dig| dig.colour.clone()).collect::<Vec<_>>();
+    let ppm = render_ppm(&polygon, &colours, 100, true);
+    let header = b"P6\n7 10\n255\n";
+    assert_eq!(&ppm[..header.len()], header);
+    let dug = ppm[header.len()..]
+        .chunks(3)
+        .filter(|rgb| rgb != &[0, 0, 0])
+        .count();
+    assert_eq!(dug, 62);
+    let trench = ppm[header.len()..]
+        .chunks(3)
+        .filter(|rgb| rgb != &[0, 0, 0] && rgb != &[0x60, 0x60, 0x60])
+        .count();
+    assert_eq!(trench, 38);
+
+    let part2_polygon = dig_polygon(data.iter().map(Dig::decode_colour));
+    let ppm = render_ppm(&part2_polygon, &colours, 50, true);
+    assert!(ppm.starts_with(b"P6\n50 "));
+
+    let svg = render_svg(&polygon, &colours, 100, false);
+    assert_eq!(svg.matches("<line ").count(), data.len());
+    assert!(svg.contains("stroke=\"#70c710\""));
 }
 
 fn main() -> std::io::Result<()>{
@@ -353,5 +528,35 @@ fn main() -> std::io::Result<()>{
     // Part 2
     println!("{}", part2(&data));
 
+    let mut svg_file = None;
+    let mut ppm_file = None;
+    let mut fill = false;
+    let mut use_part2 = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--svg" => { svg_file = Some(args.next().expect("--svg needs a filename")); }
+            "--ppm" => { ppm_file = Some(args.next().expect("--ppm needs a filename")); }
+            "--fill" => { fill = true; }
+            "--part2" => { use_part2 = true; }
+            _ => {
+                eprintln!("Unknown option {}", arg);
+                std::process::exit(1);
+            }
+        }
+    }
+    let polygon = if use_part2 {
+        dig_polygon(data.iter().map(Dig::decode_colour))
+    } else {
+        dig_polygon(data.iter().map(|dig| (dig.dir, dig.dist)))
+    };
+    let colours = data.iter().map(|dig| dig.colour.clone()).collect::<Vec<_>>();
+    if let Some(filename) = svg_file {
+        File::create(filename)?.write_all(render_svg(&polygon, &colours, 1000, fill).as_bytes())?;
+    }
+    if let Some(filename) = ppm_file {
+        File::create(filename)?.write_all(&render_ppm(&polygon, &colours, 1000, fill))?;
+    }
+
     Ok(())
 }
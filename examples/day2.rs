@@ -1,12 +1,19 @@
 #[allow(unused)]
 use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
+use std::collections::BTreeMap;
 use std::str::FromStr;
 
-#[derive(Default, Clone)]
+/// Cubes of each colour, used both for what's shown in a round and for
+/// what's in a bag.
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
 pub struct Round {
-    pub blue: usize,
-    pub green: usize,
-    pub red: usize,
+    pub cubes: BTreeMap<String, usize>,
+}
+
+impl Round {
+    pub fn count(&self, colour: &str) -> usize {
+        self.cubes.get(colour).copied().unwrap_or(0)
+    }
 }
 
 pub struct Game {
@@ -14,29 +21,22 @@ pub struct Game {
     pub rounds: Vec<Round>,
 }
 
-pub enum Handful {
-    Blue(usize),
-    Red(usize),
-    Green(usize)
+pub struct Handful {
+    colour: String,
+    count: usize,
 }
 
 regex_parser!(parse_handful: Handful {
-    BLUE = r#"\s*(\d+) blue\s*"# => |count: usize| Handful::Blue(count),
-    RED = r#"\s*(\d+) red\s*"# => |count: usize| Handful::Red(count),
-    GREEN = r#"\s*(\d+) green\s*"# => |count: usize| Handful::Green(count)
+    HANDFUL = r#"^\s*(\d+) (\w+)\s*$"# =>
+        |count: usize, colour: String| Handful { colour, count }
 });
 
 impl FromStr for Round {
     type Err = ();
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut round: Round = Default::default();
-        for handful in s.split(',').map(|s| FromStr::from_str(s).unwrap()) {
-            use Handful::*;
-            match handful {
-                Red(v) => { round.red += v; }
-                Green(v) => { round.green += v; }
-                Blue(v) => { round.blue += v; }
-            }
+        for handful in s.split(',').map(parse_handful) {
+            *round.cubes.entry(handful.colour).or_insert(0) += handful.count;
         }
         Ok(round)
     }
@@ -59,34 +59,73 @@ fn parse_input(input: &str) -> Data {
         .collect()
 }
 
-timeit!{
-fn part1(data: &Data) -> usize {
-    let mut result = 0;
-    'game: for game in data {
-        for round in &game.rounds {
-            if round.red > 12 || round.green > 13 || round.blue > 14 {
-                continue 'game;
+const PUZZLE_BAG: &str = "12 red, 13 green, 14 blue";
+const COLOURS: [&str; 3] = ["red", "green", "blue"];
+
+/// Each colour shown in a round which the bag doesn't have enough of,
+/// with how many were shown.
+fn violations<'a>(round: &'a Round, bag: &Round) -> Vec<(&'a str, usize)> {
+    round.cubes
+        .iter()
+        .filter(|&(colour, &count)| count > bag.count(colour))
+        .map(|(colour, &count)| (&colour[..], count))
+        .collect()
+}
+
+fn is_possible(game: &Game, bag: &Round) -> bool {
+    game.rounds.iter().all(|round| violations(round, bag).is_empty())
+}
+
+/// The fewest cubes of each colour which make the game possible.
+fn minimal_bag(game: &Game) -> Round {
+    let mut bag = Round::default();
+    for round in &game.rounds {
+        for (colour, &count) in &round.cubes {
+            let max = bag.cubes.entry(colour.clone()).or_insert(0);
+            *max = (*max).max(count);
+        }
+    }
+    bag
+}
+
+/// The product of the minimal bag's counts of the given colours.
+fn power(game: &Game, colours: &[&str]) -> usize {
+    let bag = minimal_bag(game);
+    colours.iter()
+        .map(|colour| bag.count(colour))
+        .product()
+}
+
+// One line for each round which couldn't come from the bag.
+fn report(data: &Data, bag: &Round) -> String {
+    let mut result = String::new();
+    for game in data {
+        for (i, round) in game.rounds.iter().enumerate() {
+            let bad = violations(round, bag);
+            if !bad.is_empty() {
+                let bad = bad.iter()
+                    .map(|(colour, count)| format!("{} {} (bag has {})", count, colour, bag.count(colour)))
+                    .collect::<Vec<_>>();
+                result.push_str(&format!("Game {} round {}: {}\n", game.id, i + 1, bad.join(", ")));
             }
         }
-        result += game.id;
     }
     result
+}
+
+timeit!{
+fn part1(data: &Data) -> usize {
+    let bag = PUZZLE_BAG.parse().unwrap();
+    data.iter()
+        .filter(|game| is_possible(game, &bag))
+        .map(|game| game.id)
+        .sum()
 }}
 timeit!{
 fn part2(data: &Data) -> usize {
-    let mut result = 0;
-    for game in data {
-        let mut max_red = 0;
-        let mut max_green = 0;
-        let mut max_blue = 0;
-        for round in &game.rounds {
-            max_red = max_red.max(round.red);
-            max_green = max_green.max(round.green);
-            max_blue = max_blue.max(round.blue);
-        }
-        result += max_red * max_green * max_blue;
-    }
-    result
+    data.iter()
+        .map(|game| power(game, &COLOURS))
+        .sum()
 }}
 
 #[test]
@@ -100,6 +139,19 @@ Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green"#;
 
     assert_eq!(part1(&data), 8);
     assert_eq!(part2(&data), 2286);
+
+    let bag: Round = PUZZLE_BAG.parse().unwrap();
+    assert_eq!(minimal_bag(&data[0]), "4 red, 2 green, 6 blue".parse().unwrap());
+    assert_eq!(report(&data, &bag),
+               "Game 3 round 1: 20 red (bag has 12)\n\
+                Game 4 round 3: 15 blue (bag has 14), 14 red (bag has 12)\n");
+
+    // Other colours work the same way
+    let game = parse_game("Game 7: 2 purple, 1 red; 5 purple, 1 orange");
+    assert!(!is_possible(&game, &bag));
+    assert!(is_possible(&game, &"5 purple, 1 red, 1 orange".parse().unwrap()));
+    assert_eq!(power(&game, &["purple", "red", "orange"]), 5);
+    assert_eq!(power(&game, &COLOURS), 0);
 }
 
 fn main() -> std::io::Result<()>{
@@ -113,5 +165,14 @@ fn main() -> std::io::Result<()>{
     // Part 2
     println!("{}", part2(&data));
 
+    // Which rounds don't fit the bag, which can be given as e.g. "3 red, 4 blue"
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--violations" {
+            let bag = args.next().unwrap_or(PUZZLE_BAG.to_string());
+            print!("{}", report(&data, &bag.parse().unwrap()));
+        }
+    }
+
     Ok(())
 }
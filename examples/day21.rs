@@ -1,171 +1,54 @@
-use std::ops::{BitOrAssign, BitAndAssign};
+use std::collections::VecDeque;
+use std::io;
 
 #[allow(unused)]
-use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
-
-type BitPiece = u64;
+use adventofcode2023::{get_input,parse_lines,regex_parser,timeit,gcd};
+use adventofcode2023::bits::{BitGrid, BitRow};
+use adventofcode2023::poly::Poly;
+use adventofcode2023::viz::{self, Cell, Colour, Control, Frame, Grid, Viewer};
 
 #[derive(Clone, Debug)]
-struct Row {
-    bits: usize,
-    // Low bits at pieces[0]
-    pieces: Vec<BitPiece>,
+struct Data {
+    plots: BitGrid,
+    spots: BitGrid,
 }
 
-impl Row {
-    const BITS: usize = BitPiece::BITS as usize;
-    pub fn new() -> Row {
-        Row {
-            bits: 0,
-            pieces: Default::default(),
-        }
-    }
-
-    fn shift_left(&mut self) {
-        let new_bits = self.bits+1;
-        let new_num_pieces = (new_bits + Self::BITS -1) / Self::BITS;
-        if new_num_pieces > self.pieces.len() {
-            self.pieces.push(0);
-        }
-
-        for i in (0..self.pieces.len()).rev() {
-            let mut newval = self.pieces[i] << 1;
-            if i > 0 {
-                newval |= self.pieces[i-1] >> (Self::BITS-1);
-            }
-            self.pieces[i] = newval;
-        }
-        self.bits = new_bits;
-    }
-
-    pub fn shift_in(&mut self, val: bool) {
-        self.shift_left();
-        if val {
-            self.pieces[0] |= 1;
-        }
-    }
-
-    fn count_ones(&self) -> usize {
-        self.pieces
-            .iter()
-            .cloned()
-            .map(|n| n.count_ones() as usize)
-            .sum()
-    }
-
-    fn or_shift_2(&mut self, other: &Row) {
-        self.bits = self.bits.max(other.bits);
-        while self.pieces.len() < other.pieces.len() {
-            self.pieces.push(0);
-        }
-        for i in 0..other.pieces.len() {
-            self.pieces[i] |= other.pieces[i] << 1;
-            self.pieces[i] |= other.pieces[i] >> 1;
-            if i > 0 {
-                self.pieces[i] |= other.pieces[i-1] >> (Self::BITS-1);
-            }
-            if i+1 < other.pieces.len() {
-                self.pieces[i] |= other.pieces[i+1] << (Self::BITS-1);
-            }
-        }
+impl Grid for Data {
+    fn width(&self) -> usize {
+        self.plots.width()
     }
-
-    fn get(&self, j: usize) -> bool {
-        let wordidx = j / Self::BITS;
-        let bitidx = j % Self::BITS;
-        if wordidx >= self.pieces.len() {
-            false
-        } else {
-            (self.pieces[wordidx] >> bitidx) & 1 == 1
-        }
+    fn height(&self) -> usize {
+        self.plots.height()
     }
-    fn set(&mut self, j: usize, val: bool) {
-        let wordidx = j / Self::BITS;
-        let bitidx = j % Self::BITS;
-        if val {
-            self.pieces[wordidx] |= 1<< bitidx;
+    fn cell(&self, x: usize, y: usize) -> Cell {
+        if !self.plots.get(x, y) {
+            '#'.into()
+        } else if self.spots.get(x, y) {
+            Cell::from('O').fg(Colour::GREEN)
         } else {
-            self.pieces[wordidx] &= !(1<< bitidx);
+            '.'.into()
         }
     }
 }
 
-impl BitOrAssign<&Row> for Row {
-    fn bitor_assign(&mut self, rhs: &Row) {
-        self.bits = self.bits.max(rhs.bits);
-        for i in 0..rhs.pieces.len() {
-            if i >= self.pieces.len() {
-                self.pieces.push(rhs.pieces[i]);
-            } else {
-                self.pieces[i] |= rhs.pieces[i];
-            }
-        }
-    }
-}
-
-impl BitAndAssign<&Row> for Row {
-    fn bitand_assign(&mut self, rhs: &Row) {
-        self.bits = self.bits.max(rhs.bits);
-        for i in 0..rhs.pieces.len() {
-            if i >= self.pieces.len() {
-                self.pieces.push(0);
-            } else {
-                self.pieces[i] &= rhs.pieces[i];
-            }
-        }
-    }
-}
-
-#[derive(Clone, Debug)]
-struct Data {
-    plots: Vec<Row>,
-    spots: Vec<Row>,
-}
-
 impl std::fmt::Display for Data {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for i in 0..self.plots.len() {
-            for j in 0..self.plots[i].bits {
-                if !self.plots[i].get(j) {
-                    write!(f, "#")?;
-                } else if self.spots[i].get(j) {
-                    write!(f, "O")?;
-                } else {
-                    write!(f, ".")?;
-                }
-            }
-            writeln!(f, "")?;
-        }
-        Ok(())
+        write!(f, "{}", Frame::capture(self).to_text())
     }
 }
 
 impl Data {
     pub fn count_spots(&self) -> usize {
-        self.spots
-            .iter()
-            .map(|row| row.count_ones())
-            .sum()
+        self.spots.count_ones()
     }
 
     pub fn next(&self) -> Self {
-        let mut new_spots = Vec::new();
-        for i in 0..self.spots.len() {
-            let mut new_row = Row::new();
-            new_row.or_shift_2(&self.spots[i]);
-            if i > 0 {
-                new_row |= &self.spots[i-1];
-            }
-            if i+1 < self.spots.len() {
-                new_row |= &self.spots[i+1];
-            }
-            new_row &= &self.plots[i];
-            new_spots.push(new_row);
-        }
+        let mut spots = self.spots.spread();
+        spots &= &self.plots;
 
         Data {
             plots: self.plots.clone(),
-            spots: new_spots,
+            spots,
         }
     }
 
@@ -175,20 +58,13 @@ fn parse_input(input: &str) -> Data {
     let mut plots = Vec::new();
     let mut spots = Vec::new();
     for row in input.lines() {
-        let mut plot = Row::new();
-        let mut spot = Row::new();
-
-        for c in row.chars() {
-            plot.shift_in(c != '#');
-            spot.shift_in(c == 'S');
-        }
-        plots.push(plot);
-        spots.push(spot);
+        plots.push(BitRow::from_bools(row.chars().map(|c| c != '#')));
+        spots.push(BitRow::from_bools(row.chars().map(|c| c == 'S')));
     }
 
     Data {
-        plots,
-        spots
+        plots: BitGrid::from_rows(plots),
+        spots: BitGrid::from_rows(spots),
     }
 }
 
@@ -201,103 +77,150 @@ fn part1(data: &Data, steps: usize) -> usize {
     data.count_spots()
 }}
 
-fn count_start_from(data: &Data, x: usize, y: usize, steps: usize) -> usize 
-{
-    let mut data = data.clone();
-    data.spots[y].set(x, true);
-
-    part1(&data, steps)
+/// Shortest distances from the start to every plot in a block of
+/// `2 * radius + 1` copies of the map in each direction, centred on the
+/// original.
+struct TileDistances {
+    width: usize,
+    height: usize,
+    radius: usize,
+    start: (usize, usize),
+    // The number of plots at each distance
+    at_dist: Vec<usize>,
 }
 
-timeit!{
-fn part2(orig_data: &Data, steps: usize) -> usize {
-    // The input has no rocks along the outer edges or the centres,
-    // so the propagation will be full speed.
-    // The result will be a large diamond with (abs(x)+abs(y)) <= steps
-    // away from the centre.
-    let mut total = 0;
-
-    let height = orig_data.spots.len();
-    let width = orig_data.spots[0].bits;
-    assert_eq!(width, height);
-    assert!(width & 1 == 1);
-    let hhei = height / 2;
-    let hwid = width / 2;
-
-    let mut data = orig_data.clone();
-    assert!(data.spots[hhei].get(hwid));
-    data.spots[hhei].set(hwid, false);
-
-    let full_from_edge_odd = count_start_from(&data, 0, hhei, 1001);
-    let full_from_edge_even = count_start_from(&data, 0, hhei, 1000);
-
-    // Full centre square (need the parity right)
-    total += part1(orig_data, steps % 200);
-
-    // Along each axis, each square (counting from centre square being -1):
-    // * Has the first edge visited at time (hwid + 1 + N*width)
-    // * Is complete at (hwid + (N+1)*width + hhei) = (N+2)*width - 1.
-    //   - Let's just say (N+2)*width = steps
-    //   - So N+2 = steps/width
-    //   -    N = (steps / width) - 2
-    // 
-    let complete_each_edge = (steps / width) - 2;
-    assert!(complete_each_edge % 2 == 0); // Don't have to worry about whether we start with even
-                                          // or odd
-    // Handle the complete ones along all four axes
-    total += (complete_each_edge/2) * (full_from_edge_odd + full_from_edge_even) * 4;
-
-    // Next, handle the incomplete ones.
-    let axis_partial_start_time = hwid + 1 + complete_each_edge * width;
-    let remain_edge_steps = steps - axis_partial_start_time;
-    total += count_start_from(&data, 0, hhei, remain_edge_steps);
-    total += count_start_from(&data, width-1, hhei, remain_edge_steps);
-    total += count_start_from(&data, hwid, 0, remain_edge_steps);
-    total += count_start_from(&data, hwid, height-1, remain_edge_steps);
-
-    if remain_edge_steps >= width {
-        // We started another
-        total += count_start_from(&data, 0, hhei, remain_edge_steps - width);
-        total += count_start_from(&data, width-1, hhei, remain_edge_steps - width);
-        total += count_start_from(&data, hwid, 0, remain_edge_steps - width);
-        total += count_start_from(&data, hwid, height-1, remain_edge_steps - width);
-    }
-
-    // Now deal with the bulk of squares off axis, which we reach from the corners.
-    // In each quadrant:
-    // One square is reached at time hwid + hhei + 2 == width + 1 == height + 1
-    // After that, the next diagonal (with one more square) is reached width steps later.
-    {
-        let mut t = height + 1;
-        let mut n = 1;
-        let mut num_odd = 0;
-        let mut num_even = 0;
-        while t <= steps {
-            let remain = steps - t;
-            if remain < 2*width {
-                let num_set_for_one =
-                    count_start_from(&data, 0, 0, remain) +
-                    count_start_from(&data, width-1, 0, remain) +
-                    count_start_from(&data, 0, height-1, remain) +
-                    count_start_from(&data, width-1, height-1, remain);
-                total += num_set_for_one * n;
-            } else if (remain & 1) == 1 {
-                num_odd += n;
-            } else {
-                num_even += n;
+impl TileDistances {
+    pub fn new(data: &Data, radius: usize) -> TileDistances {
+        let height = data.plots.height();
+        let width = data.plots.width();
+        let start = data.spots
+            .iter_ones()
+            .next()
+            .expect("No start position");
+
+        let tiles = 2 * radius + 1;
+        let block_width = tiles * width;
+        let block_height = tiles * height;
+        let mut dist = vec![u32::MAX; block_width * block_height];
+        let plots = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| data.plots.get(x, y))
+            .collect::<Vec<_>>();
+        let mut at_dist = vec![1];
+
+        let mut queue = VecDeque::new();
+        let block_start = (start.0 + radius * width, start.1 + radius * height);
+        dist[block_start.1 * block_width + block_start.0] = 0;
+        queue.push_back(block_start);
+        while let Some((x, y)) = queue.pop_front() {
+            let d = dist[y * block_width + x] + 1;
+            // Off the top or left edge wraps round to a huge coordinate.
+            for (nx, ny) in [(x.wrapping_sub(1), y), (x, y.wrapping_sub(1)), (x + 1, y), (x, y + 1)] {
+                if nx >= block_width || ny >= block_height {
+                    continue;
+                }
+                let idx = ny * block_width + nx;
+                if dist[idx] == u32::MAX && plots[(ny % height) * width + nx % width] {
+                    dist[idx] = d;
+                    if at_dist.len() <= d as usize {
+                        at_dist.push(0);
+                    }
+                    at_dist[d as usize] += 1;
+                    queue.push_back((nx, ny));
+                }
             }
-            t += width;
-            n += 1;
         }
 
-        let full_from_corner_even = count_start_from(&data, 0, 0, 1000);
-        let full_from_corner_odd = count_start_from(&data, 0, 0, 1001);
+        TileDistances {
+            width,
+            height,
+            radius,
+            start,
+            at_dist,
+        }
+    }
+
+    /// The smallest radius for which every plot within `steps` of the
+    /// start is inside the block.
+    pub fn radius_for(data: &Data, steps: usize) -> usize {
+        let min_side = data.plots.width().min(data.plots.height());
+        steps.div_ceil(min_side) + 1
+    }
+
+    /// The largest step count which can't leave the block.
+    pub fn max_steps(&self) -> usize {
+        let (x, y) = self.start;
+        let r = self.radius;
+        [x + r * self.width,
+         (r + 1) * self.width - 1 - x,
+         y + r * self.height,
+         (r + 1) * self.height - 1 - y]
+            .into_iter()
+            .min()
+            .unwrap()
+    }
 
-        total += num_odd * 4 * full_from_corner_odd;
-        total += num_even * 4 * full_from_corner_even;
+    /// The number of plots which can be reached in exactly `steps`.
+    pub fn count_reachable(&self, steps: usize) -> usize {
+        assert!(steps <= self.max_steps());
+        self.at_dist
+            .iter()
+            .take(steps + 1)
+            .skip(steps % 2)
+            .step_by(2)
+            .sum()
     }
+}
 
-    total
+// The number of periods sampled when looking for a quadratic.
+const SAMPLES: usize = 6;
+
+/// Count the plots reachable in exactly `steps` on the infinitely tiled
+/// map.
+///
+/// For large step counts, the number reachable at `base + n * period` is
+/// eventually quadratic in `n`.  This is checked on several samples taken
+/// from an exact BFS, and extrapolated if it holds; otherwise the whole
+/// area is searched.
+fn count_infinite(data: &Data, steps: usize) -> usize {
+    let height = data.plots.height();
+    let width = data.plots.width();
+
+    // Repeats of the map line up every `lcm(width, height)` steps, but
+    // parity only does when that's even.  The shorter period usually
+    // works anyway and needs a much smaller search, so try it first.
+    let period = width / gcd(width, height) * height;
+    let periods = if period % 2 == 1 { vec![period, 2 * period] } else { vec![period] };
+    for period in periods {
+        let base = steps % period + period;
+        let last = base + (SAMPLES - 1) * period;
+        if steps <= last {
+            break;
+        }
+
+        let dists = TileDistances::new(data, TileDistances::radius_for(data, last));
+        let samples = (0..SAMPLES)
+            .map(|i| dists.count_reachable(base + i * period) as i64)
+            .collect::<Vec<_>>();
+        for i in 0..=(SAMPLES - 5) {
+            // Five samples only fit a quadratic if it's really there
+            let poly = Poly::fit(&samples[i..i+5]);
+            if poly.degree() <= 2 {
+                let n = ((steps - (base + i * period)) / period) as i64;
+                return poly.eval(n) as usize;
+            }
+        }
+    }
+
+    // Too few steps to extrapolate, or no quadratic pattern found; do it
+    // the slow way.
+    TileDistances::new(data, TileDistances::radius_for(data, steps))
+        .count_reachable(steps)
+}
+
+timeit!{
+fn part2(data: &Data, steps: usize) -> usize {
+    count_infinite(data, steps)
 }}
 
 #[test]
@@ -316,8 +239,9 @@ fn test() {
     let data = parse_input(&tests);
 
     assert_eq!(part1(&data, 6), 16);
-//    assert_eq!(part2(&data, 6), 16);
-//    assert_eq!(part2(&data, 10), 60);
+    assert_eq!(data.to_string(), format!("{}\n", tests.replace('S', "O")));
+    assert_eq!(part2(&data, 6), 16);
+    assert_eq!(part2(&data, 10), 50);
     assert_eq!(part2(&data, 50), 1594);
     assert_eq!(part2(&data, 100), 6536);
     assert_eq!(part2(&data, 500), 167004);
@@ -325,6 +249,73 @@ fn test() {
     assert_eq!(part2(&data, 5000), 16733044);
 }
 
+/// Show the reachable plots after each step.
+fn visualise(data: &Data, steps: usize, viewer: &mut dyn Viewer) -> io::Result<()> {
+    let mut data = data.clone();
+    for step in 0..=steps {
+        let status = format!("Step {}: {} plots", step, data.count_spots());
+        if viewer.show_grid(&data, &status)? == Control::Quit {
+            break;
+        }
+        data = data.next();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+fn tiled(input: &str, n: usize) -> Data {
+    let mut result = String::new();
+    for ty in 0..(2*n+1) {
+        for line in input.lines() {
+            for tx in 0..(2*n+1) {
+                if (tx, ty) == (n, n) {
+                    result.push_str(line);
+                } else {
+                    result.push_str(&line.replace('S', "."));
+                }
+            }
+            result.push('\n');
+        }
+    }
+    parse_input(&result)
+}
+
+#[test]
+fn test_brute_force() {
+    let tests = r#"...........
+.....###.#.
+.###.##..#.
+..#.#...#..
+....#.#....
+.##..S####.
+.##..#...#.
+.......##..
+.##.#.####.
+.##..##.##.
+..........."#;
+    let data = parse_input(&tests);
+    let big = tiled(&tests, 4);
+    let mut spots = big.clone();
+    for steps in 0..=40 {
+        assert_eq!(count_infinite(&data, steps), spots.count_spots());
+        spots = spots.next();
+    }
+
+    // A map with no clear lines through the start or along the edges.
+    let awkward = r#"..#..
+.#...
+..S#.
+#....
+...#."#;
+    let data = parse_input(&awkward);
+    let big = tiled(&awkward, 24);
+    let mut spots = big.clone();
+    for steps in 0..=110 {
+        assert_eq!(count_infinite(&data, steps), spots.count_spots());
+        spots = spots.next();
+    }
+}
+
 fn main() -> std::io::Result<()>{
     let input = get_input(21)?;
 
@@ -336,5 +327,9 @@ fn main() -> std::io::Result<()>{
     // Part 2
     println!("{}", part2(&data, 26501365));
 
+    if let Some(mut viewer) = viz::viewer_from_args()? {
+        visualise(&data, 64, viewer.as_mut())?;
+    }
+
     Ok(())
 }
@@ -1,4 +1,8 @@
 use std::collections::{HashSet, HashMap, hash_map::Entry};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use termion::color;
 
 #[allow(unused)]
 use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
@@ -59,14 +63,21 @@ fn parse_input(input: &str) -> Data {
     }
 }
 
-fn do_part1(data: &Data) -> usize {
+/// The stack after every brick has fallen as far as it can.
+struct Settled {
+    bricks: Vec<Brick>,
+    // Map from brick to bricks it's resting on
+    under: HashMap<usize, HashSet<usize>>,
+}
+
+fn settle(data: &Data) -> Settled {
     let mut bricks = data.bricks.clone();
     bricks.sort_by_key(|brick| brick.pos0.2.min(brick.pos1.2));
 
-    let mut unsafe_bricks = HashSet::new();
+    let mut under = HashMap::new();
 
     let mut space = HashMap::new();
-    for (i, brick) in bricks.iter().enumerate() {
+    for (i, brick) in bricks.iter_mut().enumerate() {
         let brick_z = brick.pos0.2.min(brick.pos1.2);
         let mut drop_by = 1;
         while brick_z > drop_by && can_drop(brick, &space, drop_by) {
@@ -84,11 +95,21 @@ fn do_part1(data: &Data) -> usize {
                 }
             }
         }
-        if resting_on.len() == 1 {
-            unsafe_bricks.insert(resting_on.into_iter().next().unwrap());
-        }
+        under.insert(i, resting_on);
+        brick.pos0.2 -= drop_by;
+        brick.pos1.2 -= drop_by;
     }
-    bricks.len() - unsafe_bricks.len()
+    Settled { bricks, under }
+}
+
+fn do_part1(data: &Data) -> usize {
+    let settled = settle(data);
+
+    let unsafe_bricks = settled.under.values()
+        .filter(|resting_on| resting_on.len() == 1)
+        .flatten()
+        .collect::<HashSet<_>>();
+    settled.bricks.len() - unsafe_bricks.len()
 }
 
 fn can_drop(brick: &Brick, space: &HashMap<Point, usize>, drop_by: usize) -> bool {
@@ -104,36 +125,17 @@ timeit!{
 fn part1(data: &Data) -> usize {
     do_part1(data)
 }}
-fn do_part2(data: &Data) -> usize {
-    let mut bricks = data.bricks.clone();
-    bricks.sort_by_key(|brick| brick.pos0.2.min(brick.pos1.2));
-
-    // Map from brick to bricks it's resting on
-    let mut under: HashMap<usize, HashSet<usize>> = HashMap::new();
 
-    let mut space = HashMap::new();
-    for (i, brick) in bricks.iter().enumerate() {
-        let brick_z = brick.pos0.2.min(brick.pos1.2);
-        let mut drop_by = 1;
-        while brick_z > drop_by && can_drop(brick, &space, drop_by) {
-            drop_by += 1;
-        }
-        drop_by -= 1;
+/// For each settled brick, how many others would fall if it were removed.
+fn fall_counts(settled: &Settled) -> Vec<usize> {
+    (0..settled.bricks.len())
+        .map(|i| chain_len(&settled.under, i))
+        .collect()
+}
 
-        // Put the brick in place
-        let mut resting_on = HashSet::new();
-        for pos in brick.poses() {
-            space.insert((pos.0, pos.1, pos.2 - drop_by), i);
-            if let Some(blockno) = space.get(&(pos.0, pos.1, pos.2-drop_by-1)) {
-                if *blockno != i {
-                    resting_on.insert(*blockno);
-                }
-            }
-        }
-        under.insert(i, resting_on);
-    }
-    (0..bricks.len())
-        .map(|i| chain_len(&under, i))
+fn do_part2(data: &Data) -> usize {
+    fall_counts(&settle(data))
+        .into_iter()
         .sum()
 }
 
@@ -164,6 +166,100 @@ fn part2(data: &Data) -> usize {
     do_part2(data)
 }}
 
+/// Write the settled stack as a Wavefront OBJ file, one box per brick.
+/// Each brick gets its own group, named with the number of bricks which
+/// would fall if it were removed.
+fn write_obj<W: Write>(out: &mut W, settled: &Settled, falls: &[usize]) -> io::Result<()> {
+    writeln!(out, "# Settled sand bricks: {} bricks", settled.bricks.len())?;
+    for (i, brick) in settled.bricks.iter().enumerate() {
+        let lo = (brick.pos0.0.min(brick.pos1.0),
+                  brick.pos0.1.min(brick.pos1.1),
+                  brick.pos0.2.min(brick.pos1.2));
+        let hi = (brick.pos0.0.max(brick.pos1.0) + 1,
+                  brick.pos0.1.max(brick.pos1.1) + 1,
+                  brick.pos0.2.max(brick.pos1.2) + 1);
+        writeln!(out, "g brick{}_falls{}", i, falls[i])?;
+        for z in [lo.2, hi.2] {
+            for y in [lo.1, hi.1] {
+                for x in [lo.0, hi.0] {
+                    writeln!(out, "v {} {} {}", x, y, z)?;
+                }
+            }
+        }
+        // Vertex n of this box (0..8) has bit 0 for x, bit 1 for y and
+        // bit 2 for z; OBJ indices start at 1.
+        let base = i * 8 + 1;
+        for face in [[0, 2, 3, 1], [4, 5, 7, 6],
+                     [0, 1, 5, 4], [2, 6, 7, 3],
+                     [0, 4, 6, 2], [1, 3, 7, 5]] {
+            writeln!(out, "f {} {} {} {}",
+                     base + face[0], base + face[1],
+                     base + face[2], base + face[3])?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Copy, Clone)]
+enum View {
+    // Looking along y, with x across
+    XZ,
+    // Looking along x, with y across
+    YZ,
+}
+
+fn fall_colour(falls: usize) -> &'static dyn color::Color {
+    match falls {
+        0 => &color::Green,
+        1..=9 => &color::Yellow,
+        10..=99 => &color::Red,
+        _ => &color::Magenta,
+    }
+}
+
+/// Render the stack as seen from the side, showing the nearest brick in
+/// each cell coloured by how many bricks would fall if it were removed.
+fn render_slice(settled: &Settled, falls: &[usize], view: View) -> String {
+    let mut space = HashMap::new();
+    for (i, brick) in settled.bricks.iter().enumerate() {
+        for pos in brick.poses() {
+            space.insert(pos, i);
+        }
+    }
+    let max = |f: fn(&Point) -> usize| space.keys().map(f).max().unwrap_or(0);
+    let (max_across, max_depth) = match view {
+        View::XZ => (max(|p| p.0), max(|p| p.1)),
+        View::YZ => (max(|p| p.1), max(|p| p.0)),
+    };
+    let max_z = max(|p| p.2);
+
+    let mut result = String::new();
+    for z in (1..=max_z).rev() {
+        for across in 0..=max_across {
+            let front = (0..=max_depth)
+                .find_map(|depth| {
+                    let pos = match view {
+                        View::XZ => (across, depth, z),
+                        View::YZ => (depth, across, z),
+                    };
+                    space.get(&pos)
+                });
+            match front {
+                Some(&i) => {
+                    result.push_str(&format!("{}#{}",
+                                             color::Fg(fall_colour(falls[i])),
+                                             color::Fg(color::Reset)));
+                }
+                None => result.push('.'),
+            }
+        }
+        result.push_str(&format!(" {}\n", z));
+    }
+    result.push_str(&"-".repeat(max_across + 1));
+    result.push_str(" 0\n");
+    result
+}
+
 #[test]
 fn test() {
     let tests = r#"1,0,1~1,2,1
@@ -177,6 +273,22 @@ fn test() {
 
     assert_eq!(part1(&data), 5);
     assert_eq!(part2(&data), 7);
+
+    let settled = settle(&data);
+    assert_eq!(settled.bricks.iter().map(|b| b.pos0.2).collect::<Vec<_>>(),
+               vec![1, 2, 2, 3, 3, 4, 5]);
+    let falls = fall_counts(&settled);
+    assert_eq!(falls, vec![6, 0, 0, 0, 0, 1, 0]);
+
+    let mut obj = Vec::new();
+    write_obj(&mut obj, &settled, &falls).unwrap();
+    let obj = String::from_utf8(obj).unwrap();
+    assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 7 * 8);
+    assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 7 * 6);
+    assert!(obj.contains("g brick0_falls6\n"));
+
+    let slice = render_slice(&settled, &falls, View::XZ);
+    assert_eq!(slice.lines().count(), 7);
 }
 
 fn main() -> std::io::Result<()>{
@@ -190,5 +302,30 @@ fn main() -> std::io::Result<()>{
     // Part 2
     println!("{}", part2(&data));
 
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--obj" => {
+                let filename = args.next().expect("--obj needs a filename");
+                let settled = settle(&data);
+                let falls = fall_counts(&settled);
+                let mut out = BufWriter::new(File::create(filename)?);
+                write_obj(&mut out, &settled, &falls)?;
+            }
+            "--view" => {
+                let settled = settle(&data);
+                let falls = fall_counts(&settled);
+                println!("x/z:");
+                print!("{}", render_slice(&settled, &falls, View::XZ));
+                println!("y/z:");
+                print!("{}", render_slice(&settled, &falls, View::YZ));
+            }
+            _ => {
+                eprintln!("Unknown option {}", arg);
+                std::process::exit(1);
+            }
+        }
+    }
+
     Ok(())
 }
@@ -1,7 +1,8 @@
-use std::{collections::{BTreeSet, HashMap}, fmt::Debug};
+use std::{collections::{BTreeSet, HashMap}, fmt::Debug, io::IsTerminal};
 
 #[allow(unused)]
 use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
+use adventofcode2023::viz::{Cell, Colour, Frame, Grid};
 
 #[derive(Copy, Clone, Debug)]
 enum Space {
@@ -133,18 +134,54 @@ impl Data {
 
     #[allow(unused)]
     fn print(&self) {
-        for row in &self.field {
-            for spc in row {
-                print!("{}", match spc {
-                    Space::Empty => '.',
-                    Space::Wall => '#',
-                    Space::SlopeN => '^',
-                    Space::SlopeE => '>',
-                    Space::SlopeS => 'v',
-                    Space::SlopeW => '<',
-                });
+        print!("{}", Frame::capture(self).to_text());
+    }
+}
+
+impl Space {
+    fn glyph(&self) -> char {
+        match self {
+            Space::Empty => '.',
+            Space::Wall => '#',
+            Space::SlopeN => '^',
+            Space::SlopeE => '>',
+            Space::SlopeS => 'v',
+            Space::SlopeW => '<',
+        }
+    }
+}
+
+impl Grid for Data {
+    fn width(&self) -> usize {
+        self.field[0].len()
+    }
+    fn height(&self) -> usize {
+        self.field.len()
+    }
+    fn cell(&self, x: usize, y: usize) -> Cell {
+        self.field[y][x].glyph().into()
+    }
+}
+
+// The map with a walk so far marked on it.
+struct PathView<'a> {
+    state: &'a WalkState,
+    data: &'a Data,
+}
+
+impl Grid for PathView<'_> {
+    fn width(&self) -> usize {
+        self.data.width()
+    }
+    fn height(&self) -> usize {
+        self.data.height()
+    }
+    fn cell(&self, x: usize, y: usize) -> Cell {
+        match self.data.field[y][x] {
+            Space::Empty if self.state.path.contains(&(x, y)) => {
+                Cell::from('O').fg(Colour::CYAN)
             }
-            println!("");
+            spc => spc.glyph().into(),
         }
     }
 }
@@ -323,29 +360,16 @@ fn do_part2(data: &Data) -> usize {
     graph.solve()
 }
 
+// Show a walk for debugging, coloured when it's going to a terminal.
 #[allow(unused)]
 fn do_print(state: &WalkState, data: &Data) {
+    let frame = Frame::capture(&PathView { state, data });
     println!("---");
-    for (y, row) in data.field.iter().enumerate() {
-        for (x, spc) in row.iter().enumerate() {
-            print!("{}", match spc {
-                Space::Empty => {
-                    if state.path.contains(&(x, y)) {
-                        'O'
-                    } else {
-                        '.'
-                    }
-                }
-                Space::Wall => '#',
-                Space::SlopeN => '^',
-                Space::SlopeE => '>',
-                Space::SlopeS => 'v',
-                Space::SlopeW => '<',
-            });
-        }
-        println!("");
+    if std::io::stdout().is_terminal() {
+        print!("{}", frame.to_ansi());
+    } else {
+        print!("{}", frame.to_text());
     }
-    
 }
 
 #[test]
@@ -376,6 +400,7 @@ fn test() {
     let data = parse_input(&tests);
 
     assert_eq!(part1(&data), 94);
+    assert_eq!(Frame::capture(&data).to_text(), format!("{}\n", tests));
     assert_eq!(part2(&data), 154);
 }
 
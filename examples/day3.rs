@@ -1,8 +1,8 @@
 #[allow(unused)]
 use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
-use std::collections::HashMap;
+use adventofcode2023::viz::{Cell, Colour, Frame, Grid};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 struct Symbol(u8);
 
 #[derive(Debug)]
@@ -13,23 +13,43 @@ struct PartInfo {
     digits: usize,
 }
 
+type Pos = (usize, usize);
+
+// What occupies each cell of the schematic.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Content {
+    Empty,
+    Symbol(Symbol),
+    // Index into `parts`
+    Number(usize),
+}
+
 struct Data {
-    symbols: HashMap<(usize, usize), Symbol>,
+    width: usize,
+    height: usize,
+    cells: Vec<Content>,
+    symbols: Vec<(Pos, Symbol)>,
     parts: Vec<PartInfo>,
 }
 
 fn parse_input(input: &str) -> Data {
-    let mut symbols = HashMap::new();
+    let mut symbols = Vec::new();
     let mut parts = Vec::new();
+    let mut cells = Vec::new();
+    let height = input.lines().count();
+    let width = input.lines().next().map_or(0, str::len);
 
     let mut part = None;
     for (y, line) in input.lines().enumerate() {
+        assert_eq!(line.len(), width);
         for (x, c) in line.as_bytes().iter().cloned().enumerate() {
             if (x == 0 || !c.is_ascii_digit()) && part.is_some() {
                 parts.push(part.take().unwrap());
             }
             match c {
-                b'.' => {}
+                b'.' => {
+                    cells.push(Content::Empty);
+                }
                 b'0'..=b'9' => {
                     if part.is_none() {
                         part = Some(PartInfo {
@@ -43,9 +63,11 @@ fn parse_input(input: &str) -> Data {
                         part.val = (part.val * 10) + (c - b'0') as usize;
                         part.digits += 1;
                     }
+                    cells.push(Content::Number(parts.len()));
                 }
                 c => {
-                    symbols.insert((x, y), Symbol(c));
+                    symbols.push(((x, y), Symbol(c)));
+                    cells.push(Content::Symbol(Symbol(c)));
                 }
             }
         }
@@ -53,44 +75,99 @@ fn parse_input(input: &str) -> Data {
     if let Some(part) = part {
         parts.push(part);
     }
-    Data { symbols, parts }
+    Data { width, height, cells, symbols, parts }
 }
 
-timeit!{
-fn part1(data: &Data) -> usize {
-    let mut result = 0;
-    'parts: for part in &data.parts {
-        for y in part.y.saturating_sub(1)..=part.y+1 {
-            for x in part.x.saturating_sub(1)..=(part.x + part.digits) {
-                if data.symbols.contains_key(&(x, y)) {
-                    result += part.val;
-                    continue 'parts;
+impl Data {
+    pub fn at(&self, x: usize, y: usize) -> Content {
+        self.cells[y * self.width + x]
+    }
+
+    // The cells in the box from (x0, y0) to (x1, y1) inclusive, grown by
+    // one in every direction and clipped to the schematic.
+    fn around(&self, (x0, y0): Pos, (x1, y1): Pos) -> impl Iterator<Item=Content> + '_ {
+        let xs = x0.saturating_sub(1)..=(x1 + 1).min(self.width - 1);
+        (y0.saturating_sub(1)..=(y1 + 1).min(self.height - 1))
+            .flat_map(move |y| xs.clone().map(move |x| self.at(x, y)))
+    }
+
+    /// The numbers touching a cell, as indices into `parts`.
+    pub fn numbers_adjacent_to(&self, pos: Pos) -> Vec<usize> {
+        let mut result = Vec::new();
+        for content in self.around(pos, pos) {
+            if let Content::Number(i) = content {
+                // A number's cells are side by side in one row, so the
+                // window scans them one after another.
+                if result.last() != Some(&i) {
+                    result.push(i);
                 }
             }
         }
+        result
     }
-    result
-}}
-timeit!{
-fn part2(data: &Data) -> usize {
-    let mut sum = 0;
-    for ((x, y), symbol) in &data.symbols {
-        let x = *x;
-        let y = *y;
-        if let Symbol(b'*') = symbol {
-            let mut adjacent = Vec::new();
-            for part in &data.parts {
-                if x+1 >= part.x && x <= part.x+part.digits &&
-                   y+1 >= part.y && y <= part.y+1 {
-                    adjacent.push(part);
-                }
-            }
-            if adjacent.len() == 2 {
-                sum += adjacent[0].val * adjacent[1].val;
+
+    /// The symbols touching a number.
+    pub fn symbols_adjacent_to(&self, part: usize) -> Vec<(Pos, Symbol)> {
+        let part = &self.parts[part];
+        let (x0, y0) = (part.x.saturating_sub(1), part.y.saturating_sub(1));
+        let xs = x0..=(part.x + part.digits).min(self.width - 1);
+        (y0..=(part.y + 1).min(self.height - 1))
+            .flat_map(|y| xs.clone().map(move |x| (x, y)))
+            .filter_map(|(x, y)| match self.at(x, y) {
+                Content::Symbol(sym) => Some(((x, y), sym)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn is_part(&self, part: usize) -> bool {
+        !self.symbols_adjacent_to(part).is_empty()
+    }
+
+    /// The product of the numbers around each `symbol` which touches
+    /// exactly `count` numbers.
+    pub fn gear_ratios(&self, symbol: Symbol, count: usize) -> impl Iterator<Item=usize> + '_ {
+        self.symbols
+            .iter()
+            .filter(move |&&(_, sym)| sym == symbol)
+            .map(|&(pos, _)| self.numbers_adjacent_to(pos))
+            .filter(move |nums| nums.len() == count)
+            .map(|nums| nums.iter().map(|&i| self.parts[i].val).product())
+    }
+}
+
+// Numbers in green if they're parts or red if not, and symbols in yellow.
+impl Grid for Data {
+    fn width(&self) -> usize {
+        self.width
+    }
+    fn height(&self) -> usize {
+        self.height
+    }
+    fn cell(&self, x: usize, y: usize) -> Cell {
+        match self.at(x, y) {
+            Content::Empty => '.'.into(),
+            Content::Symbol(Symbol(c)) => Cell::from(c as char).fg(Colour::YELLOW),
+            Content::Number(i) => {
+                let part = &self.parts[i];
+                let digit = part.val / 10usize.pow((part.x + part.digits - 1 - x) as u32) % 10;
+                let glyph = char::from_digit(digit as u32, 10).unwrap();
+                Cell::from(glyph).fg(if self.is_part(i) { Colour::GREEN } else { Colour::RED })
             }
         }
     }
-    sum
+}
+
+timeit!{
+fn part1(data: &Data) -> usize {
+    (0..data.parts.len())
+        .filter(|&i| data.is_part(i))
+        .map(|i| data.parts[i].val)
+        .sum()
+}}
+timeit!{
+fn part2(data: &Data) -> usize {
+    data.gear_ratios(Symbol(b'*'), 2).sum()
 }}
 
 #[test]
@@ -109,6 +186,18 @@ fn test() {
 
     assert_eq!(part1(&data), 4361);
     assert_eq!(part2(&data), 467835);
+
+    assert_eq!(data.at(2, 2), Content::Number(2));
+    assert_eq!(data.numbers_adjacent_to((3, 1)), vec![0, 2]);
+    assert_eq!(data.numbers_adjacent_to((3, 4)), vec![4]);
+    assert_eq!(data.symbols_adjacent_to(4), vec![((3, 4), Symbol(b'*'))]);
+    assert_eq!(data.symbols_adjacent_to(1), vec![]);
+    assert_eq!(data.gear_ratios(Symbol(b'*'), 1).collect::<Vec<_>>(), vec![617]);
+    assert_eq!(data.gear_ratios(Symbol(b'+'), 1).collect::<Vec<_>>(), vec![592]);
+    assert_eq!(Frame::capture(&data).to_text(), format!("{}\n", tests));
+    let frame = Frame::capture(&data);
+    assert_eq!(frame.get(5, 0).fg, Some(Colour::RED));
+    assert_eq!(frame.get(0, 0).fg, Some(Colour::GREEN));
 }
 
 fn main() -> std::io::Result<()>{
@@ -122,5 +211,9 @@ fn main() -> std::io::Result<()>{
     // Part 2
     println!("{}", part2(&data));
 
+    if std::env::args().any(|arg| arg == "--render") {
+        print!("{}", Frame::capture(&data).to_ansi());
+    }
+
     Ok(())
 }
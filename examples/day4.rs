@@ -1,7 +1,7 @@
 use std::collections::HashSet;
 
 #[allow(unused)]
-use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
+use adventofcode2023::{get_input,parse_lines,regex_parser,timeit,xorshift};
 
 #[derive(Debug)]
 pub struct Card {
@@ -65,21 +65,97 @@ fn part1(data: &Data) -> usize {
     }
     sum
 }}
-timeit!{
-fn part2(data: &Data) -> usize {
-    let mut num_cards = vec![1; data.len()];
+/// How a winning card hands out copies of the cards after it.
+#[derive(Copy, Clone, Debug)]
+struct WinRule {
+    // Copies of each card won
+    copies: usize,
+    // How many following cards each matching number reaches
+    reach_per_match: usize,
+}
 
-    for i in 0..data.len() {
-        let card = &data[i];
-        let count = num_cards[i];
-        let num_wins = card.num_winning;
-        if num_wins > 0 {
-            for j in (i+1)..=(i+num_wins) {
-                num_cards[j] += count;
+impl WinRule {
+    const PUZZLE: WinRule = WinRule { copies: 1, reach_per_match: 1 };
+}
+
+/// The cascade of copies from a table of cards.  Copies won of cards past
+/// the end of the table don't exist, so they're counted separately and
+/// don't win anything themselves.
+struct Scratchcards {
+    matches: Vec<usize>,
+    rule: WinRule,
+}
+
+impl Scratchcards {
+    pub fn new(matches: Vec<usize>, rule: WinRule) -> Scratchcards {
+        Scratchcards { matches, rule }
+    }
+
+    pub fn from_cards(cards: &[Card], rule: WinRule) -> Scratchcards {
+        Scratchcards::new(cards.iter().map(|c| c.num_winning).collect(), rule)
+    }
+
+    // How many of each card there are when we start with `initial`, and
+    // how many copies were won past the end.  Each card's wins are a
+    // range, so they're added to a difference array and the running sum
+    // gives the copies arriving at each card.
+    fn cascade(&self, initial: &[usize]) -> (Vec<usize>, usize) {
+        let n = self.matches.len();
+        let mut diff = vec![0isize; n + 1];
+        let mut arriving = 0isize;
+        let mut past_end = 0;
+        let mut result = Vec::with_capacity(n);
+        for i in 0..n {
+            arriving += diff[i];
+            let count = initial[i] + arriving as usize;
+            result.push(count);
+            let reach = self.matches[i] * self.rule.reach_per_match;
+            let won = count * self.rule.copies;
+            if reach > 0 && won > 0 {
+                if i + 1 < n {
+                    diff[i + 1] += won as isize;
+                    diff[(i + 1 + reach).min(n)] -= won as isize;
+                }
+                past_end += (i + 1 + reach).saturating_sub(n) * won;
             }
         }
+        (result, past_end)
+    }
+
+    /// The number of each card held at the end, originals included.
+    pub fn copies(&self) -> Vec<usize> {
+        self.cascade(&vec![1; self.matches.len()]).0
+    }
+
+    /// The number of copies won of cards beyond the end of the table.
+    pub fn past_end(&self) -> usize {
+        self.cascade(&vec![1; self.matches.len()]).1
+    }
+
+    pub fn total(&self) -> usize {
+        self.copies().iter().sum()
+    }
+
+    /// The number of each card held which came from the original of card
+    /// `start`, itself included.
+    pub fn cascade_from(&self, start: usize) -> Vec<usize> {
+        let mut initial = vec![0; self.matches.len()];
+        initial[start] = 1;
+        self.cascade(&initial).0
     }
-    num_cards.iter().cloned().sum()
+
+    /// For each original card, how many of the copies of `card` it led
+    /// to.
+    pub fn origins(&self, card: usize) -> Vec<usize> {
+        (0..self.matches.len())
+            .map(|start| if start <= card { self.cascade_from(start)[card] } else { 0 })
+            .collect()
+    }
+}
+
+timeit!{
+fn part2(data: &Data) -> usize {
+    Scratchcards::from_cards(data, WinRule::PUZZLE).total()
 }}
 
 #[test]
@@ -94,6 +170,60 @@ Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11"#;
 
     assert_eq!(part1(&data), 13);
     assert_eq!(part2(&data), 30);
+
+    let cards = Scratchcards::from_cards(&data, WinRule::PUZZLE);
+    assert_eq!(cards.copies(), vec![1, 2, 4, 8, 14, 1]);
+    assert_eq!(cards.origins(3), vec![4, 2, 1, 1, 0, 0]);
+    let doubled = Scratchcards::from_cards(&data, WinRule { copies: 2, reach_per_match: 3 });
+    assert_eq!(doubled.copies(), vec![1, 3, 9, 27, 81, 81]);
+    assert_eq!(cards.past_end(), 0);
+    // Card 1 reaches 7 cards past the end, card 2 two, card 3 three and
+    // card 4 one.
+    assert_eq!(doubled.past_end(), 7 * 2 + 2 * 6 + 3 * 18 + 54);
+}
+
+// Hand out copies one card at a time, remembering which original each
+// one came from, and counting those won past the end.
+#[cfg(test)]
+fn naive_copies(cards: &Scratchcards) -> (Vec<Vec<usize>>, usize) {
+    let n = cards.matches.len();
+    let mut result = vec![vec![0; n]; n];
+    let mut past_end = 0;
+    let mut queue = (0..n).map(|i| (i, i)).collect::<std::collections::VecDeque<_>>();
+    while let Some((card, origin)) = queue.pop_front() {
+        result[card][origin] += 1;
+        let reach = cards.matches[card] * cards.rule.reach_per_match;
+        for won in (card + 1)..(card + 1 + reach) {
+            for _ in 0..cards.rule.copies {
+                if won < n {
+                    queue.push_back((won, origin));
+                } else {
+                    past_end += 1;
+                }
+            }
+        }
+    }
+    (result, past_end)
+}
+
+#[test]
+fn test_against_queue() {
+    let mut random = xorshift(0x2023_0404);
+    let mut rng = move |n: u64| (random() % n) as usize;
+    for _ in 0..200 {
+        let n = 1 + rng(10);
+        let matches = (0..n).map(|_| rng(4)).collect::<Vec<_>>();
+        let rule = WinRule { copies: 1 + rng(2), reach_per_match: 1 + rng(3) };
+        let cards = Scratchcards::new(matches, rule);
+        let (naive, past_end) = naive_copies(&cards);
+        let copies = cards.copies();
+        for card in 0..n {
+            assert_eq!(copies[card], naive[card].iter().sum::<usize>());
+            assert_eq!(cards.origins(card), naive[card]);
+        }
+        assert_eq!(cards.total(), copies.iter().sum::<usize>());
+        assert_eq!(cards.past_end(), past_end);
+    }
 }
 
 fn main() -> std::io::Result<()>{
@@ -107,5 +237,20 @@ fn main() -> std::io::Result<()>{
     // Part 2
     println!("{}", part2(&data));
 
+    // Where each card's copies came from.
+    if std::env::args().any(|arg| arg == "--origins") {
+        let cards = Scratchcards::from_cards(&data, WinRule::PUZZLE);
+        for (i, count) in cards.copies().into_iter().enumerate() {
+            let from = cards.origins(i)
+                .into_iter()
+                .enumerate()
+                .filter(|&(_, n)| n > 0)
+                .map(|(start, n)| format!("{}x{}", data[start].id, n))
+                .collect::<Vec<_>>();
+            println!("Card {}: {} copies from {}", data[i].id, count, from.join(" "));
+        }
+        println!("{} copies won past the last card", cards.past_end());
+    }
+
     Ok(())
 }
@@ -1,4 +1,4 @@
-use std::collections::{HashMap};
+use std::collections::{HashMap, HashSet};
 
 #[allow(unused)]
 use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
@@ -13,57 +13,152 @@ struct Map {
     map: Vec<(Id, Id, usize)>,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+enum AlmanacError {
+    // Two entries of a map cover some of the same ids, on the source
+    // side or the destination side.
+    Overlap { map: String, dest: bool, first: (Id, Id), second: (Id, Id) },
+    NoMapFrom(String),
+    Cycle(String),
+}
+
 impl Map {
-    fn lookup(&self, id: Id) -> Id {
-        let m = self.map.binary_search_by(
-            |item| {
-                if item.0 > id {
-                    std::cmp::Ordering::Greater
-                } else if (item.0 + item.2) <= id {
-                    std::cmp::Ordering::Less
-                } else {
-                    std::cmp::Ordering::Equal
+    /// Check that no id is mapped by two entries, and that no two entries
+    /// map to the same id.
+    pub fn validate(&self) -> Result<(), AlmanacError> {
+        for dest in [false, true] {
+            let mut ranges = self.map
+                .iter()
+                .map(|&(src, dst, len)| (if dest { dst } else { src }, len))
+                .collect::<Vec<_>>();
+            ranges.sort();
+            for pair in ranges.windows(2) {
+                if pair[0].0 + pair[0].1 > pair[1].0 {
+                    return Err(AlmanacError::Overlap {
+                        map: format!("{}-to-{}", self.source, self.dest),
+                        dest,
+                        first: pair[0],
+                        second: pair[1],
+                    });
                 }
-            });
-        match m {
-            Ok(idx) => {
-                let item = self.map[idx];
-                item.1 + (id - item.0)
             }
-            Err(_) => {
-                id
+        }
+        Ok(())
+    }
+}
+
+// Just past the largest id, which may not fit in an `Id`.
+const END: i128 = Id::MAX as i128 + 1;
+
+/// A function on ids which adds a constant over each of a series of
+/// ranges.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Piecewise {
+    // (start, offset) in order of start, the first starting at 0.  Each
+    // piece runs up to the start of the next.
+    pieces: Vec<(Id, i128)>,
+}
+
+impl Piecewise {
+    pub fn identity() -> Piecewise {
+        Piecewise { pieces: vec![(0, 0)] }
+    }
+
+    /// The function for one map, with unmapped ids left alone.  The map
+    /// must be valid.
+    pub fn from_map(map: &Map) -> Piecewise {
+        let mut pieces = Vec::new();
+        let mut next = 0;
+        for &(src, dst, len) in &map.map {
+            if src > next {
+                pieces.push((next, 0));
             }
+            pieces.push((src, dst as i128 - src as i128));
+            next = src + len;
+        }
+        if (next as i128) < END {
+            pieces.push((next, 0));
         }
+        Piecewise::normalised(pieces)
     }
-    // Returns the whole range
-    fn lookup_len(&self, id: Id) -> (Id, Id) {
-        let m = self.map.binary_search_by(
-            |item| {
-                if item.0 > id {
-                    std::cmp::Ordering::Greater
-                } else if (item.0 + item.2) <= id {
-                    std::cmp::Ordering::Less
-                } else {
-                    std::cmp::Ordering::Equal
-                }
-            });
-        match m {
-            Ok(idx) => {
-                let item = self.map[idx];
-                let offset = id - item.0;
-                let new_id = item.1 + offset;
-                let count = item.2 - offset; 
-                (new_id, count)
+
+    // Remove empty pieces and join neighbours with the same offset.
+    fn normalised(pieces: Vec<(Id, i128)>) -> Piecewise {
+        let mut result: Vec<(Id, i128)> = Vec::with_capacity(pieces.len());
+        for (start, offset) in pieces {
+            match result.last_mut() {
+                Some(last) if last.0 == start => last.1 = offset,
+                Some(last) if last.1 == offset => {}
+                _ => result.push((start, offset)),
             }
-            Err(idx) => {
-                if idx == self.map.len() {
-                    (id, Id::MAX)
-                } else {
-                    let item = self.map[idx];
-                    (id, item.0 - id)
+        }
+        // A replaced piece may now match the one before.
+        result.dedup_by(|b, a| a.1 == b.1);
+        Piecewise { pieces: result }
+    }
+
+    // Each piece as (start, end, offset), with the end exclusive.
+    fn spans(&self) -> impl Iterator<Item=(i128, i128, i128)> + '_ {
+        self.pieces.iter().enumerate().map(|(i, &(start, offset))| {
+            let end = self.pieces.get(i + 1).map_or(END, |p| p.0 as i128);
+            (start as i128, end, offset)
+        })
+    }
+
+    pub fn apply(&self, id: Id) -> Id {
+        let i = self.pieces.partition_point(|p| p.0 <= id) - 1;
+        (id as i128 + self.pieces[i].1) as Id
+    }
+
+    /// This function followed by `next`.
+    pub fn then(&self, next: &Piecewise) -> Piecewise {
+        let mut pieces = Vec::new();
+        for (start, end, offset) in self.spans() {
+            // Split the image of this piece at the pieces of `next`.
+            for (nstart, nend, noffset) in next.spans() {
+                let lo = nstart.max(start + offset);
+                let hi = nend.min(end + offset);
+                if lo < hi {
+                    pieces.push(((lo - offset) as Id, offset + noffset));
                 }
             }
         }
+        Piecewise::normalised(pieces)
+    }
+
+    /// Where a range of ids goes, as (start, len) ranges.
+    pub fn map_range(&self, start: Id, len: Id) -> Vec<(Id, Id)> {
+        let (start, end) = (start as i128, start as i128 + len as i128);
+        self.spans()
+            .filter_map(|(s, e, offset)| {
+                let lo = s.max(start);
+                let hi = e.min(end);
+                (lo < hi).then(|| ((lo + offset) as Id, (hi - lo) as Id))
+            })
+            .collect()
+    }
+
+    /// The ids which map into a range, as sorted (start, len) ranges.
+    pub fn inverse(&self, start: Id, len: Id) -> Vec<(Id, Id)> {
+        let (start, end) = (start as i128, start as i128 + len as i128);
+        let mut result: Vec<(i128, i128)> = self.spans()
+            .filter_map(|(s, e, offset)| {
+                let lo = (s + offset).max(start);
+                let hi = (e + offset).min(end);
+                (lo < hi).then_some((lo - offset, hi - offset))
+            })
+            .collect();
+        result.sort();
+        let mut merged: Vec<(i128, i128)> = Vec::new();
+        for (lo, hi) in result {
+            match merged.last_mut() {
+                Some(last) if last.1 == lo => last.1 = hi,
+                _ => merged.push((lo, hi)),
+            }
+        }
+        merged.into_iter()
+            .map(|(lo, hi)| (lo as Id, (hi - lo) as Id))
+            .collect()
     }
 }
 
@@ -73,43 +168,55 @@ struct Data {
 }
 
 impl Data {
-    // Map from a seed to a different type
-    pub fn map_to(&self, target: &str, seed: Id) -> Id {
-        let mut cur_type = "seed";
-        let mut id = seed;
-        while cur_type != target {
-            let map = self.maps.get(cur_type).unwrap();
-            let new_id = map.lookup(id);
-            cur_type = &map.dest;
-            id = new_id;
-        }
-        id
-    }
-    // Returns (Id, num) pairs
-    pub fn map_to_multiple(&self, target: &str, seed: Id, count: Id) -> Vec<(Id, Id)> {
+    /// Check every map, and compose the chain from seeds to `target` into
+    /// one function.
+    pub fn compose(&self, target: &str) -> Result<Piecewise, AlmanacError> {
+        let mut result = Piecewise::identity();
         let mut cur_type = "seed";
-        let mut ranges = vec![(seed, count)];
+        let mut seen = HashSet::new();
         while cur_type != target {
-            let mut new_ranges = Vec::new();
-            let map = self.maps.get(cur_type).unwrap();
-            for (start, startlen) in ranges {
-                let mut id = start;
-                let mut len = startlen;
-                while id < (start + startlen) {
-                    let (new_id, mapped_len) = map.lookup_len(id);
-                    let overlap = len.min(mapped_len);
-                    new_ranges.push((new_id, overlap));
-                    id += overlap;
-                    len -= overlap;
-                }
+            if !seen.insert(cur_type) {
+                return Err(AlmanacError::Cycle(cur_type.to_string()));
             }
+            let map = self.maps
+                .get(cur_type)
+                .ok_or_else(|| AlmanacError::NoMapFrom(cur_type.to_string()))?;
+            map.validate()?;
+            result = result.then(&Piecewise::from_map(map));
             cur_type = &map.dest;
-            ranges = new_ranges;
         }
-        ranges
+        Ok(result)
+    }
+
+    // The seeds line read as (start, len) ranges.
+    pub fn seed_ranges(&self) -> Vec<(Id, Id)> {
+        self.seeds
+            .chunks(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect()
     }
 }
 
+/// For each seed range, the lowest location reached and the first seed
+/// which gets there.
+fn lowest_locations(ranges: &[(Id, Id)], to_location: &Piecewise) -> Vec<(Id, Id)> {
+    ranges.iter()
+        .map(|&(start, len)| {
+            let location = to_location.map_range(start, len)
+                .into_iter()
+                .map(|r| r.0)
+                .min()
+                .unwrap();
+            let seed = to_location.inverse(location, 1)
+                .into_iter()
+                .map(|r| r.0.max(start))
+                .find(|&seed| seed < start + len && to_location.apply(seed) == location)
+                .unwrap();
+            (seed, location)
+        })
+        .collect()
+}
+
 fn parse_input(input: &str) -> Data {
     let mut lines = input.lines();
 
@@ -162,30 +269,22 @@ fn parse_input(input: &str) -> Data {
 
 timeit!{
 fn part1(data: &Data) -> usize {
+    let to_location = data.compose("location").unwrap();
     data.seeds
         .iter()
-        .map(|&s| {
-            data.map_to("location", s)
-        })
+        .map(|&s| to_location.apply(s))
         .min()
         .unwrap()
 }}
 timeit!{
 fn part2(data: &Data) -> usize {
-    let mut min_loc = Id::MAX;
-
-    let mut seed_iter = data.seeds.iter().cloned();
-    while let Some(seed) = seed_iter.next() {
-        let seed_count = seed_iter.next().unwrap(); 
-
-        let new_locs = data.map_to_multiple("location", seed, seed_count);
-        let new_min = new_locs.into_iter()
-                .min()
-                .unwrap()
-                .0;
-        min_loc = min_loc.min(new_min);
-    }
-    min_loc
+    let to_location = data.compose("location").unwrap();
+    data.seed_ranges()
+        .into_iter()
+        .flat_map(|(start, len)| to_location.map_range(start, len))
+        .map(|r| r.0)
+        .min()
+        .unwrap()
 }}
 
 #[test]
@@ -225,13 +324,38 @@ humidity-to-location map:
 56 93 4"#;
     let data = parse_input(&tests);
 
-    assert_eq!(data.map_to("location", 79), 82);
-    assert_eq!(data.map_to("location", 14), 43);
-    assert_eq!(data.map_to("location", 55), 86);
-    assert_eq!(data.map_to("location", 13), 35);
+    let to_location = data.compose("location").unwrap();
+    assert_eq!(to_location.apply(79), 82);
+    assert_eq!(to_location.apply(14), 43);
+    assert_eq!(to_location.apply(55), 86);
+    assert_eq!(to_location.apply(13), 35);
+    assert_eq!(data.compose("soil").unwrap().apply(79), 81);
+    assert_eq!(data.compose("humidity").unwrap().apply(14), 43);
 
     assert_eq!(part1(&data), 35);
     assert_eq!(part2(&data), 46);
+
+    // Composing the maps gives the same as following them one by one.
+    let maps = ["seed", "soil", "fertilizer", "water", "light", "temperature", "humidity"]
+        .map(|name| Piecewise::from_map(&data.maps[name]));
+    for seed in 0..200 {
+        let stepwise = maps.iter().fold(seed, |id, map| map.apply(id));
+        assert_eq!(to_location.apply(seed), stepwise);
+        let back = to_location.inverse(stepwise, 1);
+        assert!(back.contains(&(seed, 1)));
+    }
+    assert_eq!(to_location.inverse(46, 1), vec![(82, 1)]);
+    assert_eq!(Piecewise::identity().then(&to_location), to_location);
+    assert_eq!(lowest_locations(&data.seed_ranges(), &to_location), vec![(82, 46), (62, 56)]);
+
+    // Broken almanacs
+    let overlap = parse_input("seeds: 1\n\nseed-to-soil map:\n50 98 2\n52 50 49");
+    assert_eq!(overlap.compose("soil"),
+               Err(AlmanacError::Overlap { map: "seed-to-soil".to_string(), dest: false,
+                                           first: (50, 49), second: (98, 2) }));
+    let overlap = parse_input("seeds: 1\n\nseed-to-soil map:\n50 98 2\n51 50 48");
+    assert!(matches!(overlap.compose("soil"), Err(AlmanacError::Overlap { dest: true, .. })));
+    assert_eq!(data.compose("flower"), Err(AlmanacError::NoMapFrom("location".to_string())));
 }
 
 fn main() -> std::io::Result<()>{
@@ -245,5 +369,15 @@ fn main() -> std::io::Result<()>{
     // Part 2
     println!("{}", part2(&data));
 
+    if std::env::args().any(|arg| arg == "--report") {
+        let to_location = data.compose("location").unwrap();
+        let lowest = lowest_locations(&data.seed_ranges(), &to_location);
+        let best = lowest.iter().map(|l| l.1).min().unwrap();
+        for ((start, len), (seed, location)) in data.seed_ranges().into_iter().zip(lowest) {
+            println!("{:>12} +{:<12} seed {:>12} -> {:>12}{}", start, len, seed, location,
+                     if location == best { " *" } else { "" });
+        }
+    }
+
     Ok(())
 }
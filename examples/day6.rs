@@ -1,10 +1,12 @@
+use std::ops::RangeInclusive;
+
 #[allow(unused)]
-use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
+use adventofcode2023::{get_input,parse_lines,regex_parser,timeit,xorshift};
 
 #[derive(Debug)]
 struct Race {
-    time: usize,
-    distance: usize,
+    time: u128,
+    distance: u128,
 }
 
 type Data = Vec<Race>;
@@ -15,14 +17,14 @@ fn parse_input(input: &str) -> Data {
                          .split_whitespace()
                          .skip(1)
                          .map(|s| s.parse().unwrap())
-                         .collect::<Vec<usize>>();
+                         .collect::<Vec<u128>>();
 
     let distances = lines.next()
                          .unwrap()
                          .split_whitespace()
                          .skip(1)
                          .map(|s| s.parse().unwrap())
-                         .collect::<Vec<usize>>();
+                         .collect::<Vec<u128>>();
 
     assert_eq!(lines.next(), None);
     times.into_iter()
@@ -43,8 +45,8 @@ fn parse_input2(input: &str) -> Race {
                         .chars()
                         .filter(|&c| '0' <= c && c <= '9')
                         .collect::<String>();
-    let time: usize = time_str.parse().unwrap();
-    let distance: usize = dist_str.parse().unwrap();
+    let time: u128 = time_str.parse().unwrap();
+    let distance: u128 = dist_str.parse().unwrap();
 
     assert_eq!(lines.next(), None);
 
@@ -53,49 +55,75 @@ fn parse_input2(input: &str) -> Race {
     }
 }
 
-timeit!{
-fn part1(data: &Data) -> usize {
-    let mut result = 1;
+impl Race {
+    pub fn beats_record(&self, hold: u128) -> bool {
+        // Anything too far to fit in a u128 is past the record.
+        hold <= self.time && hold.checked_mul(self.time - hold).is_none_or(|d| d > self.distance)
+    }
 
-    for race in data {
+    /// The hold times which beat the record, if any.
+    pub fn winning_holds(&self) -> Option<RangeInclusive<u128>> {
         // t = time
         // n = length of press
         // distance = (t - n) * n
-        // When matching the given distance d:
-        // d = (t-n)*n
-        // n^2 - t*n + d = 0
-        let t = race.time as f64;
-        let d = race.distance as f64 + 0.000000001;
-
-        let cross1 = (t - (t*t - 4.0*d).sqrt()) / 2.0;
-        let cross2 = (t + (t*t - 4.0*d).sqrt()) / 2.0;
-        let cross1 = cross1.ceil();
-        let cross2 = cross2.floor();
-
-        let num_ways = (cross2 - cross1) + 1.0;
-        result *= num_ways as usize;
+        // To beat d we need (t-n)*n >= d+1, which is between the roots of
+        // n^2 - t*n + (d+1) = 0, ie (t +- sqrt(t^2 - 4(d+1))) / 2.
+        let t = self.time;
+        let Some(square) = t.checked_mul(t) else {
+            return self.search_holds();
+        };
+        // If 4(d+1) doesn't fit then it's more than t^2 and can't be won.
+        let need = self.distance.checked_add(1)?.checked_mul(4)?;
+        let disc = square.checked_sub(need)?;
+        let mut lo = (t - disc.isqrt()).div_ceil(2);
+        // The integer square root can leave us one out either way.
+        while lo > 0 && self.beats_record(lo - 1) {
+            lo -= 1;
+        }
+        while lo <= t / 2 && !self.beats_record(lo) {
+            lo += 1;
+        }
+        if lo > t / 2 {
+            return None;
+        }
+        // The distance is symmetric about t/2.
+        Some(lo..=(t - lo))
+    }
+
+    // The winning holds found by binary search, for races too long to
+    // square.  The distance only goes up until t/2.
+    fn search_holds(&self) -> Option<RangeInclusive<u128>> {
+        let t = self.time;
+        if !self.beats_record(t / 2) {
+            return None;
+        }
+        let (mut lo, mut hi) = (0, t / 2);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.beats_record(mid) {
+                hi = mid;
+            } else {
+                lo = mid + 1;
+            }
+        }
+        Some(lo..=(t - lo))
+    }
+
+    pub fn num_ways(&self) -> u128 {
+        self.winning_holds()
+            .map_or(0, |holds| holds.end() - holds.start() + 1)
     }
+}
 
-    result
+timeit!{
+fn part1(data: &Data) -> u128 {
+    data.iter()
+        .map(Race::num_ways)
+        .product()
 }}
 timeit!{
-fn part2(race: &Race) -> usize {
-    // t = time
-    // n = length of press
-    // distance = (t - n) * n
-    // When matching the given distance d:
-    // d = (t-n)*n
-    // n^2 - t*n + d = 0
-    let t = race.time as f64;
-    let d = race.distance as f64 + 0.000000001;
-
-    let cross1 = (t - (t*t - 4.0*d).sqrt()) / 2.0;
-    let cross2 = (t + (t*t - 4.0*d).sqrt()) / 2.0;
-    let cross1 = cross1.ceil();
-    let cross2 = cross2.floor();
-
-    let num_ways = (cross2 - cross1) + 1.0;
-    num_ways as usize
+fn part2(race: &Race) -> u128 {
+    race.num_ways()
 }}
 
 #[test]
@@ -108,6 +136,44 @@ Distance:  9  40  200"#;
 
     let data2 = parse_input2(&tests);
     assert_eq!(part2(&data2), 71503);
+
+    assert_eq!(data[0].winning_holds(), Some(2..=5));
+    // Equalling the record isn't enough
+    assert_eq!(Race { time: 4, distance: 4 }.winning_holds(), None);
+    assert_eq!(Race { time: 4, distance: 3 }.winning_holds(), Some(2..=2));
+    assert_eq!(Race { time: 0, distance: 0 }.num_ways(), 0);
+    // Far beyond what an f64 can resolve
+    let big = Race { time: 1 << 60, distance: (1 << 118) - 1 };
+    assert_eq!(big.winning_holds(), Some((1 << 59)..=(1 << 59)));
+    let big = Race { time: (1 << 60) + 1, distance: (1 << 118) + (1 << 59) - 1 };
+    assert_eq!(big.num_ways(), 2);
+    // Too long to square the time
+    let huge = Race { time: 1 << 64, distance: (1 << 126) - 1 };
+    assert_eq!(huge.winning_holds(), Some((1 << 63)..=(1 << 63)));
+    let huge = Race { time: u128::MAX, distance: u128::MAX };
+    assert_eq!(huge.winning_holds(), Some(2..=(u128::MAX - 2)));
+    assert!(!huge.beats_record(1));
+    assert_eq!(Race { time: 10, distance: u128::MAX }.winning_holds(), None);
+    assert_eq!(Race { time: 1 << 63, distance: u128::MAX / 4 }.winning_holds(), None);
+}
+
+#[test]
+fn test_against_brute_force() {
+    let mut random = xorshift(0x2023_0606);
+    let mut rng = move |n: u64| (random() % n) as u128;
+    for _ in 0..2000 {
+        let time = rng(200);
+        // Mostly near the best possible distance, where the roots are
+        // close together.
+        let best = (time / 2) * (time - time / 2);
+        let distance = best.saturating_sub(rng(2 * time as u64 + 2));
+        let race = Race { time, distance };
+        let wins = (0..=time).filter(|&n| race.beats_record(n)).collect::<Vec<_>>();
+        assert_eq!(race.num_ways(), wins.len() as u128, "{:?}", race);
+        if let Some(holds) = race.winning_holds() {
+            assert_eq!((*holds.start(), *holds.end()), (wins[0], *wins.last().unwrap()));
+        }
+    }
 }
 
 fn main() -> std::io::Result<()>{
@@ -1,18 +1,6 @@
 #[allow(unused)]
 use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
 
-fn card_value(card: char) -> u8 {
-    match card {
-        '0'..='9' => (card as u8) - b'0',
-        'T' => 10,
-        'J' => 11,
-        'Q' => 12,
-        'K' => 13,
-        'A' => 14,
-        _ => panic!("Invalid card [{card}]"),
-    }
-}
-
 #[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug)]
 enum HandValue {
     HighCard,
@@ -24,142 +12,187 @@ enum HandValue {
     Five,
 }
 
-#[derive(Ord, Eq, Clone)]
-struct Hand {
-    hand_value: HandValue,
-    cards: [u8;5],
+impl HandValue {
+    // The five-card hand type with these group sizes, largest first.
+    fn from_groups(groups: &[u8]) -> Option<HandValue> {
+        Some(match groups {
+            [5] => HandValue::Five,
+            [4, 1] => HandValue::Four,
+            [3, 2] => HandValue::FullHouse,
+            [3, 1, 1] => HandValue::Three,
+            [2, 2, 1] => HandValue::TwoPair,
+            [2, 1, 1, 1] => HandValue::Pair,
+            [1, 1, 1, 1, 1] => HandValue::HighCard,
+            _ => return None,
+        })
+    }
+}
 
-    bid: usize,
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum CardError {
+    UnknownCard(char),
+    WrongSize(usize),
 }
 
-impl PartialEq for Hand {
-    fn eq(&self, other: &Self) -> bool {
-        self.hand_value == other.hand_value && self.cards == other.cards
-    }
+/// How ties between hands of the same type are broken.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Tiebreak {
+    // Compare the first card, then the second, and so on
+    InOrder,
+    // Compare the highest card of each, then the next highest...
+    Highest,
 }
 
-impl PartialOrd for Hand {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        match self.hand_value.partial_cmp(&other.hand_value) {
-            Some(core::cmp::Ordering::Equal) => {}
-            ord => return ord,
-        }
-        self.cards.partial_cmp(&other.cards)
-    }
+/// A set of rules for scoring hands.
+#[derive(Clone, Debug)]
+struct CamelCards {
+    // Every card, weakest first
+    order: Vec<char>,
+    // Cards which join whichever group makes the hand strongest
+    wild: Vec<char>,
+    hand_size: usize,
+    tiebreak: Tiebreak,
 }
 
-type Data = Vec<Hand>;
-fn parse_input(input: &str) -> Data {
-    let mut result = Vec::new();
+/// A hand's strength; hands compare by their groups first.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Strength {
+    // Group sizes, largest first, with wild cards added to the largest
+    groups: Vec<u8>,
+    // Card ranks in tiebreak order
+    ranks: Vec<u8>,
+}
 
-    for line in input.lines() {
-        let (cardstr, bidstr) = line.split_once(' ').unwrap();
-        let bid = bidstr.parse().unwrap();
+#[derive(Clone, Debug)]
+struct Ranked<'a> {
+    hand: &'a Hand,
+    strength: Strength,
+    rank: usize,
+}
 
-        let mut cards = [0u8;5];
-        for (i, card) in cardstr.chars().map(card_value).enumerate() {
-            cards[i] = card;
+impl CamelCards {
+    pub fn standard() -> CamelCards {
+        CamelCards {
+            order: "23456789TJQKA".chars().collect(),
+            wild: vec![],
+            hand_size: 5,
+            tiebreak: Tiebreak::InOrder,
         }
+    }
 
-        let hand_value = calc_hand_value(&cards);
+    /// J is now the weakest card, but wild.
+    pub fn jokers() -> CamelCards {
+        CamelCards {
+            order: "J23456789TQKA".chars().collect(),
+            wild: vec!['J'],
+            ..CamelCards::standard()
+        }
+    }
 
-        result.push(Hand {
-            hand_value, cards, bid
-        });
+    fn card_rank(&self, card: char) -> Result<u8, CardError> {
+        self.order
+            .iter()
+            .position(|&c| c == card)
+            .map(|i| i as u8)
+            .ok_or(CardError::UnknownCard(card))
     }
 
-    result
-}
+    pub fn strength(&self, cards: &str) -> Result<Strength, CardError> {
+        let size = cards.chars().count();
+        if size != self.hand_size {
+            return Err(CardError::WrongSize(size));
+        }
+        let mut ranks = cards.chars()
+            .map(|c| self.card_rank(c))
+            .collect::<Result<Vec<_>, _>>()?;
 
-fn calc_hand_value(cards: &[u8; 5]) -> HandValue {
-    let mut counts = [0u8; 256];
+        let mut counts = vec![0u8; self.order.len()];
+        let mut wild = 0;
+        for card in cards.chars() {
+            if self.wild.contains(&card) {
+                wild += 1;
+            } else {
+                counts[self.card_rank(card)? as usize] += 1;
+            }
+        }
+        let mut groups = counts.into_iter()
+            .filter(|&c| c > 0)
+            .collect::<Vec<_>>();
+        groups.sort_by(|a, b| b.cmp(a));
+        match groups.first_mut() {
+            Some(largest) => *largest += wild,
+            None => groups.push(wild),
+        }
 
-    for &v in cards {
-        counts[v as usize] += 1;
+        if self.tiebreak == Tiebreak::Highest {
+            ranks.sort_by(|a, b| b.cmp(a));
+        }
+        Ok(Strength { groups, ranks })
     }
 
-    let mut countv = counts.into_iter()
-                           .filter(|&c| c>0)
-                           .collect::<Vec<_>>();
-    countv.sort();
-    match &countv[..] {
-        &[5] => HandValue::Five,
-        &[1, 4] => HandValue::Four,
-        &[2, 3] => HandValue::FullHouse,
-        &[1, 1, 3] => HandValue::Three,
-        &[1, 2, 2] => HandValue::TwoPair,
-        &[1, 1, 1, 2] => HandValue::Pair,
-        &[1, 1, 1, 1, 1] => HandValue::HighCard,
-        _ => panic!("Invalid hand: {:?}", &countv),
+    /// Describe how a hand is scored.
+    pub fn explain(&self, cards: &str) -> Result<String, CardError> {
+        let strength = self.strength(cards)?;
+        let kind = match HandValue::from_groups(&strength.groups) {
+            Some(value) => format!("{:?}", value),
+            None => strength.groups
+                .iter()
+                .map(|g| g.to_string())
+                .collect::<Vec<_>>()
+                .join("+"),
+        };
+        let wild = cards.chars().filter(|c| self.wild.contains(c)).count();
+        let mut result = format!("{}: {}", cards, kind);
+        if wild > 0 {
+            result.push_str(&format!(" with {} wild", wild));
+        }
+        result.push_str(&format!(", ties broken on {:?}", strength.ranks));
+        Ok(result)
     }
-}
-
-fn calc_hand_value_2(cards: &[u8; 5]) -> HandValue {
-    let mut counts = [0u8; 256];
 
-    for &v in cards {
-        counts[v as usize] += 1;
+    /// The hands from weakest to strongest, with their ranks.
+    pub fn rank<'a>(&self, hands: &'a [Hand]) -> Result<Vec<Ranked<'a>>, CardError> {
+        let mut result = hands.iter()
+            .map(|hand| Ok(Ranked { hand, strength: self.strength(&hand.cards)?, rank: 0 }))
+            .collect::<Result<Vec<_>, _>>()?;
+        result.sort_by(|a, b| a.strength.cmp(&b.strength));
+        for (i, ranked) in result.iter_mut().enumerate() {
+            ranked.rank = i + 1;
+        }
+        Ok(result)
     }
 
-    // Keep the jokers back
-    let joker_index = card_value('J') as usize;
-    let num_jokers = counts[joker_index];
-    counts[joker_index] = 0;
-
-    let mut countv = counts.into_iter()
-                           .filter(|&c| c>0)
-                           .collect::<Vec<_>>();
-    countv.sort();
-    if countv.len() == 0 {
-        countv.push(num_jokers);
-    } else {
-        *countv.last_mut().unwrap() += num_jokers;
-    }
-    match &countv[..] {
-        &[5] => HandValue::Five,
-        &[1, 4] => HandValue::Four,
-        &[2, 3] => HandValue::FullHouse,
-        &[1, 1, 3] => HandValue::Three,
-        &[1, 2, 2] => HandValue::TwoPair,
-        &[1, 1, 1, 2] => HandValue::Pair,
-        &[1, 1, 1, 1, 1] => HandValue::HighCard,
-        _ => panic!("Invalid hand: {:?}", &countv),
+    pub fn winnings(&self, hands: &[Hand]) -> Result<usize, CardError> {
+        Ok(self.rank(hands)?
+            .iter()
+            .map(|r| r.rank * r.hand.bid)
+            .sum())
     }
 }
 
+#[derive(Clone, Debug)]
+struct Hand {
+    cards: String,
+    bid: usize,
+}
+
+type Data = Vec<Hand>;
+fn parse_input(input: &str) -> Data {
+    input.lines()
+        .map(|line| {
+            let (cards, bidstr) = line.split_once(' ').unwrap();
+            Hand { cards: cards.to_string(), bid: bidstr.parse().unwrap() }
+        })
+        .collect()
+}
+
 timeit!{
 fn part1(data: &Data) -> usize {
-    let mut data = data.iter().cloned().collect::<Vec<_>>();
-    data.sort();
-    data.into_iter()
-        .enumerate()
-        .map(|(i, hand)| {
-            (i+1) * hand.bid
-        })
-        .sum()
+    CamelCards::standard().winnings(data).unwrap()
 }}
 timeit!{
 fn part2(data: &Data) -> usize {
-    let mut data = data.iter().cloned().collect::<Vec<_>>();
-
-    // Update the hand values
-    for hand in &mut data {
-        // Update the hand value
-        hand.hand_value = calc_hand_value_2(&hand.cards);
-        // Update Joker values
-        for v in &mut hand.cards {
-            if *v == 11 {
-                *v = 0;
-            }
-        }
-    }
-    data.sort();
-    data.into_iter()
-        .enumerate()
-        .map(|(i, hand)| {
-            (i+1) * hand.bid
-        })
-        .sum()
+    CamelCards::jokers().winnings(data).unwrap()
 }}
 
 #[test]
@@ -173,6 +206,33 @@ QQQJA 483"#;
 
     assert_eq!(part1(&data), 6440);
     assert_eq!(part2(&data), 5905);
+
+    let jokers = CamelCards::jokers();
+    assert_eq!(jokers.explain("KTJJT").unwrap(),
+               "KTJJT: Four with 2 wild, ties broken on [11, 9, 0, 0, 9]");
+    assert_eq!(CamelCards::standard().explain("KTJJT").unwrap(),
+               "KTJJT: TwoPair, ties broken on [11, 8, 9, 9, 8]");
+    assert_eq!(jokers.explain("JJJJJ").unwrap(),
+               "JJJJJ: Five with 5 wild, ties broken on [0, 0, 0, 0, 0]");
+    let ranked = jokers.rank(&data).unwrap();
+    assert_eq!(ranked.iter().map(|r| &r.hand.cards[..]).collect::<Vec<_>>(),
+               vec!["32T3K", "KK677", "T55J5", "QQQJA", "KTJJT"]);
+    assert_eq!(jokers.strength("1234X"), Err(CardError::UnknownCard('1')));
+    assert_eq!(jokers.strength("23456A"), Err(CardError::WrongSize(6)));
+
+    // Six-card hands, two wild cards and poker-style ties
+    let custom = CamelCards {
+        order: "23456789TJQKA".chars().collect(),
+        wild: vec!['2', '3'],
+        hand_size: 6,
+        tiebreak: Tiebreak::Highest,
+    };
+    assert_eq!(custom.explain("A2KK3Q").unwrap(),
+               "A2KK3Q: 4+1+1 with 2 wild, ties broken on [12, 11, 11, 10, 1, 0]");
+    let hands = parse_input("AKQJT9 1\n9TJQKA 10\n222222 100\nAA23KK 1000");
+    let ranked = custom.rank(&hands).unwrap();
+    assert_eq!(ranked.iter().map(|r| r.hand.bid).collect::<Vec<_>>(), vec![1, 10, 1000, 100]);
+    assert_eq!(ranked[0].strength, ranked[1].strength);
 }
 
 fn main() -> std::io::Result<()>{
@@ -186,5 +246,13 @@ fn main() -> std::io::Result<()>{
     // Part 2
     println!("{}", part2(&data));
 
+    if std::env::args().any(|arg| arg == "--explain") {
+        let rules = CamelCards::jokers();
+        for r in rules.rank(&data).unwrap() {
+            println!("{:4} x {:4} = {:7}  {}", r.rank, r.hand.bid, r.rank * r.hand.bid,
+                     rules.explain(&r.hand.cards).unwrap());
+        }
+    }
+
     Ok(())
 }
@@ -59,53 +59,197 @@ fn part1(data: &Data) -> usize {
     steps
 }}
 
-// Returns number of steps and ending point
-fn steps_to<'a>(data: &'a Data, start_step: usize, start: &'a str) -> (usize, &'a str) {
-    let mut pos = start;
-    let mut steps = start_step;
-    let mut moves = data.insns.iter().cloned().cycle().skip(start_step);
-    loop {
-        let entry = data.map.get(pos).unwrap();
-        let mv = moves.next().unwrap();
-        match mv {
-            b'L' => { pos = &entry.0; }
-            b'R' => { pos = &entry.1; }
-            _ => panic!()
+/// The map with nodes numbered, for walking quickly.
+struct Network<'a> {
+    names: Vec<&'a str>,
+    // The left and right node from each node
+    next: Vec<[usize; 2]>,
+    // Whether each instruction is R
+    insns: Vec<bool>,
+}
+
+/// Where one walk is on a goal node.  The walk's state is the node and
+/// position in the instructions, so after `prefix` steps it repeats every
+/// `cycle` steps.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Walk {
+    prefix: usize,
+    cycle: usize,
+    // Steps (at least one) before the cycle starts which land on a goal
+    prefix_hits: Vec<usize>,
+    // Offsets from `prefix` within the cycle which land on a goal
+    cycle_hits: Vec<usize>,
+}
+
+impl Walk {
+    pub fn hits_at(&self, step: usize) -> bool {
+        if step < self.prefix {
+            self.prefix_hits.contains(&step)
+        } else {
+            self.cycle_hits.contains(&((step - self.prefix) % self.cycle))
         }
-        steps += 1;
-        if pos.ends_with('Z') {
-            break;
+    }
+
+    // The steps from the cycle on, as (residue, modulus) pairs.
+    fn residues(&self) -> Vec<(i128, i128)> {
+        self.cycle_hits
+            .iter()
+            .map(|&r| (((self.prefix + r) % self.cycle) as i128, self.cycle as i128))
+            .collect()
+    }
+}
+
+/// Why no step has every walk on a goal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Unsolvable {
+    NeverHits(String),
+    Conflict(String, String),
+    NoCommonStep,
+}
+
+impl std::fmt::Display for Unsolvable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Unsolvable::NeverHits(start) => write!(f, "The walk from {} never reaches a goal", start),
+            Unsolvable::Conflict(a, b) => write!(f, "The walks from {} and {} are never on goals at the same step", a, b),
+            Unsolvable::NoCommonStep => write!(f, "No step has every walk on a goal"),
+        }
+    }
+}
+
+impl<'a> Network<'a> {
+    pub fn new(data: &'a Data) -> Network<'a> {
+        let mut names = data.map.keys().map(|s| s.as_str()).collect::<Vec<_>>();
+        names.sort();
+        let index = |name: &str| names.binary_search(&name).unwrap();
+        let next = names.iter()
+            .map(|&name| {
+                let (left, right) = &data.map[name];
+                [index(left), index(right)]
+            })
+            .collect();
+        let insns = data.insns
+            .iter()
+            .map(|&c| match c {
+                b'L' => false,
+                b'R' => true,
+                _ => panic!("Bad instruction {}", c as char),
+            })
+            .collect();
+        Network { names, next, insns }
+    }
+
+    #[cfg(test)]
+    pub fn node(&self, name: &str) -> usize {
+        self.names.binary_search(&name).unwrap()
+    }
+
+    /// Follow the instructions from `start` until the state repeats.
+    pub fn walk(&self, start: usize, is_goal: impl Fn(&str) -> bool) -> Walk {
+        let n = self.insns.len();
+        let mut first_seen = vec![usize::MAX; self.names.len() * n];
+        let mut hits = Vec::new();
+        let mut node = start;
+        for step in 0.. {
+            let state = node * n + step % n;
+            if first_seen[state] != usize::MAX {
+                let prefix = first_seen[state];
+                let (before, after): (Vec<_>, Vec<_>) = hits.into_iter().partition(|&h| h < prefix);
+                return Walk {
+                    prefix,
+                    cycle: step - prefix,
+                    prefix_hits: before.into_iter().filter(|&h| h > 0).collect(),
+                    cycle_hits: after.into_iter().map(|h| h - prefix).collect(),
+                };
+            }
+            first_seen[state] = step;
+            if is_goal(self.names[node]) {
+                hits.push(step);
+            }
+            node = self.next[node][self.insns[step % n] as usize];
         }
+        unreachable!()
     }
-    (steps, pos)
 }
 
-fn lcm(a: usize, b: usize) -> usize {
-    let g = adventofcode2023::gcd(a, b);
-    (a / g) * b
+// Solve x = a (mod m), x = b (mod n), if possible.
+fn crt((a, m): (i128, i128), (b, n): (i128, i128)) -> Option<(i128, i128)> {
+    let g = adventofcode2023::gcd(m as usize, n as usize) as i128;
+    if (b - a) % g != 0 {
+        return None;
+    }
+    // Find the inverse of m/g modulo n/g with the extended Euclidean
+    // algorithm.
+    let (mg, ng) = (m / g, n / g);
+    let (mut r0, mut r1, mut s0, mut s1) = (mg.rem_euclid(ng), ng, 1i128, 0i128);
+    while r1 != 0 {
+        let q = r0 / r1;
+        (r0, r1) = (r1, r0 - q * r1);
+        (s0, s1) = (s1, s0 - q * s1);
+    }
+    let lcm = m * ng;
+    let k = ((b - a) / g * s0).rem_euclid(ng);
+    Some(((a + m * k).rem_euclid(lcm), lcm))
+}
+
+/// The first step (at least one) where every walk is on a goal.
+fn first_common_hit(walks: &[(&str, Walk)]) -> Result<usize, Unsolvable> {
+    if let Some((start, _)) = walks.iter().find(|(_, w)| w.prefix_hits.is_empty() && w.cycle_hits.is_empty()) {
+        return Err(Unsolvable::NeverHits(start.to_string()));
+    }
+    // Before every walk is in its cycle, just try each step.
+    let settled = walks.iter().map(|(_, w)| w.prefix).max().unwrap_or(0).max(1);
+    if let Some(step) = (1..settled).find(|&step| walks.iter().all(|(_, w)| w.hits_at(step))) {
+        return Ok(step);
+    }
+
+    // After that, combine the residues of every walk's goal steps.
+    let mut combined = vec![(0, 1)];
+    for (_, walk) in walks {
+        let mut next = Vec::new();
+        for &c in &combined {
+            for r in walk.residues() {
+                if let Some(x) = crt(c, r) {
+                    if !next.contains(&x) {
+                        next.push(x);
+                    }
+                }
+            }
+        }
+        combined = next;
+    }
+    let settled = settled as i128;
+    if let Some(step) = combined.iter()
+        .map(|&(r, m)| r + (settled - r + m - 1).div_euclid(m) * m)
+        .min() {
+        return Ok(step as usize);
+    }
+
+    // Work out why not.
+    for (i, (a, wa)) in walks.iter().enumerate() {
+        for (b, wb) in &walks[i+1..] {
+            if wa.residues().iter().all(|&x| wb.residues().iter().all(|&y| crt(x, y).is_none())) {
+                return Err(Unsolvable::Conflict(a.to_string(), b.to_string()));
+            }
+        }
+    }
+    Err(Unsolvable::NoCommonStep)
+}
+
+// The walks from every node ending in A to any ending in Z.
+fn ghost_walks<'a>(network: &Network<'a>) -> Vec<(&'a str, Walk)> {
+    network.names
+        .iter()
+        .enumerate()
+        .filter(|(_, name)| name.ends_with('A'))
+        .map(|(i, &name)| (name, network.walk(i, |n| n.ends_with('Z'))))
+        .collect()
 }
 
 timeit!{
-fn part2(data: &Data) -> usize {
-    let poses = data.map
-        .keys()
-        .filter(|k| k.ends_with('A'))
-        .map(|s| s.as_str())
-        .collect::<Vec<&str>>();
-
-    let mut first_hits = Vec::new();
-    let mut repeat = Vec::new();
-    for pos in poses {
-        let (first, endpos) = steps_to(data, 0, pos);
-        let (second, _) = steps_to(data, first, endpos);
-        first_hits.push(first);
-        repeat.push(second - first);
-    }
-    // It turns out that they all repeat at the ends of the list of
-    // instructions, so we don't need to do any complicated CRT or
-    // anything.
-    repeat.into_iter()
-          .fold(1, |a, b| lcm(a, b))
+fn part2(data: &Data) -> Result<usize, Unsolvable> {
+    let network = Network::new(data);
+    first_common_hit(&ghost_walks(&network))
 }}
 
 #[test]
@@ -142,7 +286,41 @@ ZZZ = (ZZZ, ZZZ)
 22C = (22Z, 22Z)
 22Z = (22B, 22B)
 XXX = (XXX, XXX)"#;
-    assert_eq!(part2(&parse_input(test_part2)), 6);
+    assert_eq!(part2(&parse_input(test_part2)), Ok(6));
+
+    let data = parse_input(test_part2);
+    let network = Network::new(&data);
+    assert_eq!(network.walk(network.node("22A"), |n| n.ends_with('Z')),
+               Walk { prefix: 1, cycle: 6, prefix_hits: vec![], cycle_hits: vec![2, 5] });
+
+    // The first goal isn't a whole cycle from the start
+    let offset = r#"L
+
+11A = (11Z, 11Z)
+11Z = (11B, 11B)
+11B = (11C, 11C)
+11C = (11Z, 11Z)
+22A = (22B, 22B)
+22B = (22Z, 22Z)
+22Z = (22B, 22B)"#;
+    assert_eq!(part2(&parse_input(offset)), Ok(4));
+
+    let data = parse_input(&format!("{}\n33A = (33B, 33B)\n33B = (33C, 33C)\n33C = (33Z, 33Z)\n\
+                                        33Z = (33D, 33D)\n33D = (33A, 33A)", offset));
+    assert_eq!(part2(&data), Ok(28));
+    let data = parse_input(&format!("{}\n33A = (33A, 33A)", offset));
+    assert_eq!(part2(&data), Err(Unsolvable::NeverHits("33A".to_string())));
+
+    let clash = r#"L
+
+11A = (11Z, 11Z)
+11Z = (11B, 11B)
+11B = (11Z, 11Z)
+22A = (22B, 22B)
+22B = (22Z, 22Z)
+22Z = (22B, 22B)"#;
+    let err = part2(&parse_input(clash)).unwrap_err();
+    assert_eq!(err.to_string(), "The walks from 11A and 22A are never on goals at the same step");
 }
 
 fn main() -> std::io::Result<()>{
@@ -154,7 +332,10 @@ fn main() -> std::io::Result<()>{
     println!("{}", part1(&data));
 
     // Part 2
-    println!("{}", part2(&data));
+    match part2(&data) {
+        Ok(steps) => println!("{}", steps),
+        Err(why) => println!("{}", why),
+    }
 
     Ok(())
 }
@@ -1,7 +1,8 @@
 #[allow(unused)]
 use adventofcode2023::{get_input,parse_lines,regex_parser,timeit};
+use adventofcode2023::poly::Poly;
 
-type Data = Vec<Vec<isize>>;
+type Data = Vec<Vec<i64>>;
 fn parse_input(input: &str) -> Data {
     input.lines()
          .map(|l| {
@@ -13,56 +14,16 @@ fn parse_input(input: &str) -> Data {
 }
 
 timeit!{
-fn part1(data: &Data) -> isize {
-    let mut sum = 0;
-    for seq in data {
-        let mut derivs = vec![seq.clone()];
-        // Calculate the derivatives
-        loop {
-            let mut vals = Vec::new();
-            for pair in derivs.last().unwrap().windows(2) {
-                vals.push(pair[1] - pair[0]);
-            }
-            if vals.iter().all(|v| *v == 0) {
-                break;
-            } else {
-                derivs.push(vals);
-            }
-        }
-        // Now extrapolate
-        let mut inc = 0;
-        while let Some(mut v) = derivs.pop() {
-            inc = v.pop().unwrap() + inc;
-        }
-        sum += inc;
-    }
-    sum
+fn part1(data: &Data) -> i64 {
+    data.iter()
+        .map(|seq| Poly::fit(seq).eval(seq.len() as i64))
+        .sum()
 }}
 timeit!{
-fn part2(data: &Data) -> isize {
-    let mut sum = 0;
-    for seq in data {
-        let mut derivs = vec![seq.clone()];
-        // Calculate the derivatives
-        loop {
-            let mut vals = Vec::new();
-            for pair in derivs.last().unwrap().windows(2) {
-                vals.push(pair[1] - pair[0]);
-            }
-            if vals.iter().all(|v| *v == 0) {
-                break;
-            } else {
-                derivs.push(vals);
-            }
-        }
-        // Now extrapolate
-        let mut inc = 0;
-        while let Some(mut v) = derivs.pop() {
-            inc = *v.first().unwrap() - inc;
-        }
-        sum += inc;
-    }
-    sum
+fn part2(data: &Data) -> i64 {
+    data.iter()
+        .map(|seq| Poly::fit(seq).eval(-1))
+        .sum()
 }}
 
 #[test]
@@ -74,6 +35,19 @@ fn test() {
 
     assert_eq!(part1(&data), 114);
     assert_eq!(part2(&data), 2);
+
+    let polys = data.iter().map(|seq| Poly::fit(seq)).collect::<Vec<_>>();
+    assert_eq!(polys.iter().map(Poly::degree).collect::<Vec<_>>(), vec![1, 2, 3]);
+    assert_eq!(polys[1].differences(), &[1, 2, 1]);
+    // Triangular numbers, (x+1)(x+2)/2
+    assert_eq!(polys[1].eval(999), 500500);
+    assert_eq!(polys[1].eval(-3), 1);
+    assert_eq!(polys[2].eval(-1), 5);
+    assert_eq!(polys[2].eval(-2), -4);
+    assert_eq!(Poly::fit(&[7, 7, 7]).degree(), 0);
+    assert_eq!(Poly::fit(&[]).eval(5), 0);
+    // x^3 a long way out
+    assert_eq!(Poly::fit(&[0, 1, 8, 27]).eval(-1_000_000), -1_000_000_000_000_000_000);
 }
 
 fn main() -> std::io::Result<()>{
@@ -0,0 +1,388 @@
+//! Fixed-width bitsets for bit-parallel grid algorithms.
+//!
+//! Rows are stored as contiguous `u64` words with bit 0 of word 0 being
+//! index 0, and any bits beyond the row's length always clear.  Binary
+//! operations work word by word over equal-length slices so the compiler
+//! can vectorise them.
+
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
+
+type Word = u64;
+const WORD_BITS: usize = Word::BITS as usize;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BitRow {
+    len: usize,
+    words: Vec<Word>,
+}
+
+impl BitRow {
+    /// A row of `len` clear bits.
+    pub fn new(len: usize) -> BitRow {
+        BitRow {
+            len,
+            words: vec![0; len.div_ceil(WORD_BITS)],
+        }
+    }
+
+    pub fn from_bools<I: IntoIterator<Item = bool>>(bools: I) -> BitRow {
+        let mut result = BitRow::new(0);
+        for b in bools {
+            if result.len.is_multiple_of(WORD_BITS) {
+                result.words.push(0);
+            }
+            result.len += 1;
+            result.set(result.len - 1, b);
+        }
+        result
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn words(&self) -> &[Word] {
+        &self.words
+    }
+
+    pub fn get(&self, i: usize) -> bool {
+        assert!(i < self.len);
+        (self.words[i / WORD_BITS] >> (i % WORD_BITS)) & 1 == 1
+    }
+
+    pub fn set(&mut self, i: usize, val: bool) {
+        assert!(i < self.len);
+        let bit = 1 << (i % WORD_BITS);
+        if val {
+            self.words[i / WORD_BITS] |= bit;
+        } else {
+            self.words[i / WORD_BITS] &= !bit;
+        }
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.words
+            .iter()
+            .map(|w| w.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn any(&self) -> bool {
+        self.words.iter().any(|&w| w != 0)
+    }
+
+    pub fn clear(&mut self) {
+        self.words.iter_mut().for_each(|w| *w = 0);
+    }
+
+    /// Move every bit `n` places towards higher indices, dropping any
+    /// which fall off the end.
+    pub fn shl(&self, n: usize) -> BitRow {
+        let mut result = BitRow::new(self.len);
+        let word_shift = n / WORD_BITS;
+        let bit_shift = n % WORD_BITS;
+        for i in word_shift..self.words.len() {
+            let src = i - word_shift;
+            let mut w = self.words[src] << bit_shift;
+            if bit_shift > 0 && src > 0 {
+                w |= self.words[src - 1] >> (WORD_BITS - bit_shift);
+            }
+            result.words[i] = w;
+        }
+        result.mask_tail();
+        result
+    }
+
+    /// Move every bit `n` places towards index 0, dropping any which fall
+    /// off the start.
+    pub fn shr(&self, n: usize) -> BitRow {
+        let mut result = BitRow::new(self.len);
+        let word_shift = n / WORD_BITS;
+        let bit_shift = n % WORD_BITS;
+        for i in 0..self.words.len().saturating_sub(word_shift) {
+            let src = i + word_shift;
+            let mut w = self.words[src] >> bit_shift;
+            if bit_shift > 0 && src + 1 < self.words.len() {
+                w |= self.words[src + 1] << (WORD_BITS - bit_shift);
+            }
+            result.words[i] = w;
+        }
+        result
+    }
+
+    /// The indices of all set bits, in increasing order.
+    pub fn iter_ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words
+            .iter()
+            .enumerate()
+            .flat_map(|(i, &w)| {
+                let mut w = w;
+                std::iter::from_fn(move || {
+                    if w == 0 {
+                        None
+                    } else {
+                        let bit = w.trailing_zeros() as usize;
+                        w &= w - 1;
+                        Some(i * WORD_BITS + bit)
+                    }
+                })
+            })
+    }
+
+    // Clear any bits beyond the end of the row.
+    fn mask_tail(&mut self) {
+        let extra = self.len % WORD_BITS;
+        if extra > 0 {
+            if let Some(last) = self.words.last_mut() {
+                *last &= (1 << extra) - 1;
+            }
+        }
+    }
+
+    fn zip_with(&mut self, rhs: &BitRow, f: impl Fn(Word, Word) -> Word) {
+        assert_eq!(self.len, rhs.len);
+        for (a, &b) in self.words.iter_mut().zip(rhs.words.iter()) {
+            *a = f(*a, b);
+        }
+    }
+}
+
+impl BitOrAssign<&BitRow> for BitRow {
+    fn bitor_assign(&mut self, rhs: &BitRow) {
+        self.zip_with(rhs, |a, b| a | b);
+    }
+}
+
+impl BitAndAssign<&BitRow> for BitRow {
+    fn bitand_assign(&mut self, rhs: &BitRow) {
+        self.zip_with(rhs, |a, b| a & b);
+    }
+}
+
+impl BitXorAssign<&BitRow> for BitRow {
+    fn bitxor_assign(&mut self, rhs: &BitRow) {
+        self.zip_with(rhs, |a, b| a ^ b);
+    }
+}
+
+impl BitOr for &BitRow {
+    type Output = BitRow;
+    fn bitor(self, rhs: &BitRow) -> BitRow {
+        let mut result = self.clone();
+        result |= rhs;
+        result
+    }
+}
+
+impl BitAnd for &BitRow {
+    type Output = BitRow;
+    fn bitand(self, rhs: &BitRow) -> BitRow {
+        let mut result = self.clone();
+        result &= rhs;
+        result
+    }
+}
+
+impl BitXor for &BitRow {
+    type Output = BitRow;
+    fn bitxor(self, rhs: &BitRow) -> BitRow {
+        let mut result = self.clone();
+        result ^= rhs;
+        result
+    }
+}
+
+impl Not for &BitRow {
+    type Output = BitRow;
+    fn not(self) -> BitRow {
+        let mut result = BitRow {
+            len: self.len,
+            words: self.words.iter().map(|w| !w).collect(),
+        };
+        result.mask_tail();
+        result
+    }
+}
+
+/// A rectangle of bits, stored as one `BitRow` per row.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BitGrid {
+    width: usize,
+    rows: Vec<BitRow>,
+}
+
+impl BitGrid {
+    pub fn new(width: usize, height: usize) -> BitGrid {
+        BitGrid {
+            width,
+            rows: vec![BitRow::new(width); height],
+        }
+    }
+
+    /// Build a grid from rows, which must all be the same length.
+    pub fn from_rows(rows: Vec<BitRow>) -> BitGrid {
+        let width = rows.first().map(|r| r.len()).unwrap_or(0);
+        assert!(rows.iter().all(|r| r.len() == width));
+        BitGrid { width, rows }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn rows(&self) -> &[BitRow] {
+        &self.rows
+    }
+
+    pub fn row(&self, y: usize) -> &BitRow {
+        &self.rows[y]
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.rows[y].get(x)
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, val: bool) {
+        self.rows[y].set(x, val);
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.rows
+            .iter()
+            .map(|r| r.count_ones())
+            .sum()
+    }
+
+    /// All set positions as `(x, y)`, row by row.
+    pub fn iter_ones(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.rows
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| row.iter_ones().map(move |x| (x, y)))
+    }
+
+    /// Every position orthogonally adjacent to a set bit (not including
+    /// the set bits themselves, unless they're also adjacent to one).
+    pub fn spread(&self) -> BitGrid {
+        let mut rows = Vec::with_capacity(self.rows.len());
+        for (y, row) in self.rows.iter().enumerate() {
+            let mut new_row = &row.shl(1) | &row.shr(1);
+            if y > 0 {
+                new_row |= &self.rows[y - 1];
+            }
+            if y + 1 < self.rows.len() {
+                new_row |= &self.rows[y + 1];
+            }
+            rows.push(new_row);
+        }
+        BitGrid {
+            width: self.width,
+            rows,
+        }
+    }
+
+    fn zip_with(&mut self, rhs: &BitGrid, f: impl Fn(&mut BitRow, &BitRow)) {
+        assert_eq!(self.rows.len(), rhs.rows.len());
+        for (a, b) in self.rows.iter_mut().zip(rhs.rows.iter()) {
+            f(a, b);
+        }
+    }
+}
+
+impl BitOrAssign<&BitGrid> for BitGrid {
+    fn bitor_assign(&mut self, rhs: &BitGrid) {
+        self.zip_with(rhs, |a, b| *a |= b);
+    }
+}
+
+impl BitAndAssign<&BitGrid> for BitGrid {
+    fn bitand_assign(&mut self, rhs: &BitGrid) {
+        self.zip_with(rhs, |a, b| *a &= b);
+    }
+}
+
+impl BitXorAssign<&BitGrid> for BitGrid {
+    fn bitxor_assign(&mut self, rhs: &BitGrid) {
+        self.zip_with(rhs, |a, b| *a ^= b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Widths either side of a word boundary, and one spanning three words.
+    const WIDTHS: [usize; 4] = [63, 64, 65, 130];
+
+    // A fixed pattern with no period that lines up with the word size.
+    fn pattern(len: usize) -> Vec<bool> {
+        (0..len).map(|i| (i * i + i / 3) % 5 < 2).collect()
+    }
+
+    fn tail_clear(row: &BitRow) -> bool {
+        let extra = row.len() % WORD_BITS;
+        extra == 0 || row.words().last().unwrap() >> extra == 0
+    }
+
+    #[test]
+    fn get_set_count() {
+        for width in WIDTHS {
+            let bools = pattern(width);
+            let mut row = BitRow::from_bools(bools.iter().copied());
+            assert_eq!(row.len(), width);
+            assert_eq!(row.words().len(), width.div_ceil(WORD_BITS));
+            assert!((0..width).all(|i| row.get(i) == bools[i]));
+            assert_eq!(row.count_ones(), bools.iter().filter(|&&b| b).count());
+            assert_eq!(row.iter_ones().collect::<Vec<_>>(),
+                       (0..width).filter(|&i| bools[i]).collect::<Vec<_>>());
+
+            row.set(width - 1, true);
+            row.set(0, false);
+            assert!(row.get(width - 1));
+            assert!(!row.get(0));
+            assert_eq!(row.iter_ones().last(), Some(width - 1));
+            assert!(tail_clear(&row));
+        }
+    }
+
+    #[test]
+    fn shifts() {
+        for width in WIDTHS {
+            let bools = pattern(width);
+            let row = BitRow::from_bools(bools.iter().copied());
+            for n in [0, 1, 63, 64, 65, width - 1, width, width + 1] {
+                let expected = BitRow::from_bools(
+                    (0..width).map(|i| i >= n && bools[i - n]));
+                let shifted = row.shl(n);
+                assert_eq!(shifted, expected, "shl({}) at width {}", n, width);
+                assert!(tail_clear(&shifted));
+
+                let expected = BitRow::from_bools(
+                    (0..width).map(|i| i + n < width && bools[i + n]));
+                assert_eq!(row.shr(n), expected, "shr({}) at width {}", n, width);
+            }
+        }
+    }
+
+    #[test]
+    fn not_masks_tail() {
+        for width in WIDTHS {
+            let bools = pattern(width);
+            let row = BitRow::from_bools(bools.iter().copied());
+            let inverted = !&row;
+            assert!(tail_clear(&inverted));
+            assert_eq!(inverted.count_ones(), width - row.count_ones());
+            assert_eq!(!&BitRow::new(width), BitRow::from_bools(vec![true; width]));
+            assert_eq!((&row | &inverted).count_ones(), width);
+            assert!(!(&row & &inverted).any());
+        }
+    }
+}
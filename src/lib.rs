@@ -1,6 +1,13 @@
 extern crate lazy_static;
 extern crate regex;
 
+pub mod bits;
+pub mod pipes;
+pub mod poly;
+pub mod polygon;
+pub mod tokens;
+pub mod viz;
+
 use std::fs::File;
 use std::str::FromStr;
 use std::fmt::Debug;
@@ -96,3 +103,16 @@ pub fn gcd(a: usize, b: usize) -> usize {
     }
     a
 }
+
+/// A small seeded pseudo-random number generator, for sampling and for
+/// randomised tests which need to be repeatable.  The seed must be
+/// non-zero.
+pub fn xorshift(mut state: u64) -> impl FnMut() -> u64 {
+    assert_ne!(state, 0, "xorshift needs a non-zero seed");
+    move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    }
+}
@@ -0,0 +1,250 @@
+//! Mazes of pipes, as in `|-LJ7F`, with one loop through a start tile `S`.
+//!
+//! The shape of the pipe under `S` is worked out from its neighbours.  The
+//! loop is then a lattice polygon through the tile centres, so the number
+//! of tiles it encloses comes from Pick's theorem.
+
+use crate::polygon::Polygon;
+use crate::viz::{Cell, Colour, Grid};
+
+pub type Pos = (usize, usize);
+
+pub const NORTH: u8 = 1;
+pub const EAST: u8 = 2;
+pub const SOUTH: u8 = 4;
+pub const WEST: u8 = 8;
+
+const DIRS: [u8; 4] = [NORTH, EAST, SOUTH, WEST];
+
+fn opposite(dir: u8) -> u8 {
+    match dir {
+        NORTH => SOUTH,
+        EAST => WEST,
+        SOUTH => NORTH,
+        WEST => EAST,
+        _ => unreachable!(),
+    }
+}
+
+/// The directions a pipe tile connects to.  Ground, the start and
+/// anything else which isn't a pipe connect nowhere.
+pub fn connections(tile: u8) -> u8 {
+    match tile {
+        b'|' => NORTH | SOUTH,
+        b'-' => EAST | WEST,
+        b'L' => NORTH | EAST,
+        b'J' => NORTH | WEST,
+        b'7' => SOUTH | WEST,
+        b'F' => SOUTH | EAST,
+        _ => 0,
+    }
+}
+
+/// The pipe tile connecting in the given directions.
+pub fn pipe_for(dirs: u8) -> u8 {
+    *b"|-LJ7F"
+        .iter()
+        .find(|&&t| connections(t) == dirs)
+        .expect("Not a pipe shape")
+}
+
+fn box_char(dirs: u8) -> char {
+    match pipe_for(dirs) {
+        b'|' => '│',
+        b'-' => '─',
+        b'L' => '└',
+        b'J' => '┘',
+        b'7' => '┐',
+        b'F' => '┌',
+        _ => unreachable!(),
+    }
+}
+
+pub struct Maze {
+    width: usize,
+    height: usize,
+    // Connections of each tile, with the start filled in
+    tiles: Vec<u8>,
+    start: Pos,
+    // The loop's tiles in order from the start
+    path: Vec<Pos>,
+    on_loop: Vec<bool>,
+}
+
+impl Maze {
+    pub fn parse(input: &str) -> Maze {
+        let rows = input.lines().map(str::as_bytes).collect::<Vec<_>>();
+        let height = rows.len();
+        let width = rows[0].len();
+        let mut tiles = Vec::with_capacity(width * height);
+        let mut start = None;
+        for (y, row) in rows.iter().enumerate() {
+            assert_eq!(row.len(), width);
+            for (x, &b) in row.iter().enumerate() {
+                if b == b'S' {
+                    start = Some((x, y));
+                }
+                tiles.push(connections(b));
+            }
+        }
+        let start = start.expect("No start tile");
+        let mut maze = Maze {
+            width,
+            height,
+            tiles,
+            start,
+            path: Vec::new(),
+            on_loop: vec![false; width * height],
+        };
+
+        // Try each pair of neighbours which connect back to the start
+        // until one of them goes round a loop.
+        let candidates = DIRS.iter()
+            .copied()
+            .filter(|&d| {
+                maze.step(start, d)
+                    .is_some_and(|pos| maze.tiles[maze.index(pos)] & opposite(d) != 0)
+            })
+            .collect::<Vec<_>>();
+        let start_index = maze.index(start);
+        'pairs: for (i, &a) in candidates.iter().enumerate() {
+            for &b in &candidates[i+1..] {
+                maze.tiles[start_index] = a | b;
+                if let Some(path) = maze.trace() {
+                    maze.path = path;
+                    break 'pairs;
+                }
+            }
+        }
+        assert!(!maze.path.is_empty(), "No loop through the start");
+        for i in 0..maze.path.len() {
+            let index = maze.index(maze.path[i]);
+            maze.on_loop[index] = true;
+        }
+        maze
+    }
+
+    fn index(&self, (x, y): Pos) -> usize {
+        y * self.width + x
+    }
+
+    fn step(&self, (x, y): Pos, dir: u8) -> Option<Pos> {
+        match dir {
+            NORTH if y > 0 => Some((x, y - 1)),
+            EAST if x + 1 < self.width => Some((x + 1, y)),
+            SOUTH if y + 1 < self.height => Some((x, y + 1)),
+            WEST if x > 0 => Some((x - 1, y)),
+            _ => None,
+        }
+    }
+
+    // Follow the pipes from the start, if they lead back to it.
+    fn trace(&self) -> Option<Vec<Pos>> {
+        let mut path = vec![self.start];
+        let mut pos = self.start;
+        let mut came_from = 0;
+        loop {
+            let tile = self.tiles[self.index(pos)];
+            let dir = DIRS.into_iter().find(|&d| tile & d != 0 && d != came_from)?;
+            pos = self.step(pos, dir)?;
+            if self.tiles[self.index(pos)] & opposite(dir) == 0 {
+                return None;
+            }
+            if pos == self.start {
+                return Some(path);
+            }
+            path.push(pos);
+            came_from = opposite(dir);
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn start(&self) -> Pos {
+        self.start
+    }
+
+    /// The pipe under the start tile.
+    pub fn start_pipe(&self) -> u8 {
+        pipe_for(self.tiles[self.index(self.start)])
+    }
+
+    /// The loop's tiles in order, starting at the start.
+    pub fn path(&self) -> &[Pos] {
+        &self.path
+    }
+
+    pub fn on_loop(&self, pos: Pos) -> bool {
+        self.on_loop[self.index(pos)]
+    }
+
+    /// The loop through the tile centres.
+    pub fn polygon(&self) -> Polygon {
+        Polygon::from_vertices(self.path
+            .iter()
+            .map(|&(x, y)| (x as isize, y as isize))
+            .collect())
+    }
+
+    /// The number of tiles inside the loop.
+    pub fn enclosed_count(&self) -> usize {
+        self.polygon().interior_points() as usize
+    }
+
+    /// The tiles inside the loop, in reading order.  Scanning along each
+    /// row, we're inside after crossing an odd number of loop tiles which
+    /// connect north.
+    pub fn enclosed_tiles(&self) -> Vec<Pos> {
+        let mut result = Vec::new();
+        for y in 0..self.height {
+            let mut inside = false;
+            for x in 0..self.width {
+                if self.on_loop((x, y)) {
+                    if self.tiles[self.index((x, y))] & NORTH != 0 {
+                        inside = !inside;
+                    }
+                } else if inside {
+                    result.push((x, y));
+                }
+            }
+        }
+        result
+    }
+}
+
+// The loop in box-drawing characters, with the start highlighted and the
+// enclosed tiles marked.
+impl Grid for Maze {
+    fn width(&self) -> usize {
+        self.width
+    }
+    fn height(&self) -> usize {
+        self.height
+    }
+    fn cell(&self, x: usize, y: usize) -> Cell {
+        if self.on_loop((x, y)) {
+            let cell = Cell::from(box_char(self.tiles[self.index((x, y))]));
+            if (x, y) == self.start {
+                cell.fg(Colour::YELLOW)
+            } else {
+                cell
+            }
+        } else {
+            // Counting crossings to the west is quick enough for drawing.
+            let crossings = (0..x)
+                .filter(|&x0| self.on_loop((x0, y)) && self.tiles[self.index((x0, y))] & NORTH != 0)
+                .count();
+            if crossings % 2 == 1 {
+                Cell::from('I').fg(Colour::GREEN)
+            } else {
+                Cell::from(' ')
+            }
+        }
+    }
+}
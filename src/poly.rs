@@ -0,0 +1,51 @@
+//! Polynomials fitted exactly to integer sequences.
+//!
+//! A sequence sampled at 0, 1, 2, ... is stored in Newton's forward
+//! difference form, `p(x) = sum d_k * C(x, k)`, where `d_k` is the first
+//! entry of the k-th row of the difference table.  Everything stays in
+//! integers, so extrapolating a long way (or backwards) is exact.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Poly {
+    // Leading forward differences, without trailing zeros
+    diffs: Vec<i64>,
+}
+
+impl Poly {
+    /// The polynomial of least degree through `samples[x]` at each `x`.
+    pub fn fit(samples: &[i64]) -> Poly {
+        let mut diffs = Vec::with_capacity(samples.len());
+        let mut row = samples.to_vec();
+        while !row.is_empty() {
+            diffs.push(row[0]);
+            row = row.windows(2).map(|w| w[1] - w[0]).collect();
+        }
+        while diffs.last() == Some(&0) {
+            diffs.pop();
+        }
+        Poly { diffs }
+    }
+
+    /// The degree, with the zero polynomial counted as degree 0.
+    pub fn degree(&self) -> usize {
+        self.diffs.len().saturating_sub(1)
+    }
+
+    /// The leading entry of each row of the difference table.
+    pub fn differences(&self) -> &[i64] {
+        &self.diffs
+    }
+
+    /// The value at `x`, which can be any integer.
+    pub fn eval(&self, x: i64) -> i64 {
+        let x = x as i128;
+        let mut result = 0i128;
+        // C(x, k), extended to negative x as x(x-1)...(x-k+1)/k!
+        let mut binom = 1i128;
+        for (k, &d) in self.diffs.iter().enumerate() {
+            result += d as i128 * binom;
+            binom = binom * (x - k as i128) / (k as i128 + 1);
+        }
+        result.try_into().expect("Polynomial value overflows i64")
+    }
+}
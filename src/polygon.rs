@@ -0,0 +1,84 @@
+//! Simple polygons with vertices on the integer lattice.
+//!
+//! Areas come from the shoelace formula and lattice point counts from
+//! Pick's theorem (`A = I + B/2 - 1`), so the cost is linear in the number
+//! of vertices however large the coordinates are.
+
+use crate::gcd;
+
+pub type Point = (isize, isize);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Polygon {
+    vertices: Vec<Point>,
+}
+
+impl Polygon {
+    /// Build a polygon from its corners in order; the last connects back
+    /// to the first.
+    pub fn from_vertices(vertices: Vec<Point>) -> Polygon {
+        Polygon { vertices }
+    }
+
+    /// Build a polygon by walking from the origin, where each step is a
+    /// unit direction and a distance.  The walk must end back where it
+    /// started.
+    pub fn from_steps<I: IntoIterator<Item = (Point, isize)>>(steps: I) -> Polygon {
+        let mut pos = (0, 0);
+        let mut vertices = Vec::new();
+        for ((dx, dy), dist) in steps {
+            vertices.push(pos);
+            pos.0 += dx * dist;
+            pos.1 += dy * dist;
+        }
+        assert_eq!(pos, (0, 0), "Polygon isn't closed");
+        Polygon { vertices }
+    }
+
+    pub fn vertices(&self) -> &[Point] {
+        &self.vertices
+    }
+
+    /// Each edge as (start, end).
+    pub fn edges(&self) -> impl Iterator<Item = (Point, Point)> + '_ {
+        self.vertices
+            .iter()
+            .zip(self.vertices.iter().cycle().skip(1))
+            .map(|(&a, &b)| (a, b))
+    }
+
+    /// Twice the enclosed area, which is always an integer.
+    pub fn double_area(&self) -> isize {
+        self.edges()
+            .map(|(a, b)| a.0 * b.1 - b.0 * a.1)
+            .sum::<isize>()
+            .abs()
+    }
+
+    /// The length of the boundary.  Only meaningful for rectilinear
+    /// polygons, where it's the same as the number of boundary points.
+    pub fn perimeter(&self) -> isize {
+        self.edges()
+            .map(|(a, b)| (b.0 - a.0).abs() + (b.1 - a.1).abs())
+            .sum()
+    }
+
+    /// The number of lattice points on the boundary.
+    pub fn boundary_points(&self) -> isize {
+        self.edges()
+            .map(|(a, b)| gcd((b.0 - a.0).unsigned_abs(), (b.1 - a.1).unsigned_abs()) as isize)
+            .sum()
+    }
+
+    /// The number of lattice points strictly inside.
+    pub fn interior_points(&self) -> isize {
+        (self.double_area() - self.boundary_points() + 2) / 2
+    }
+
+    /// The number of lattice points inside or on the boundary.  When each
+    /// point is a unit cell, this is the area covered including the
+    /// boundary cells.
+    pub fn lattice_points(&self) -> isize {
+        self.interior_points() + self.boundary_points()
+    }
+}
@@ -0,0 +1,180 @@
+//! Finding every occurrence of a set of words in a string.
+//!
+//! `TokenScanner` builds an Aho–Corasick automaton from a table of words
+//! and the value each stands for, so one pass over the text finds all the
+//! matches, including ones which overlap ("eightwo" has both "eight" and
+//! "two").
+
+use std::collections::{HashMap, VecDeque};
+
+/// One match of a word, with its byte range in the text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token<'a, V> {
+    pub start: usize,
+    pub end: usize,
+    pub value: &'a V,
+}
+
+#[derive(Default)]
+struct Node {
+    next: HashMap<u8, usize>,
+    // Longest proper suffix which is also a prefix of some word
+    fail: usize,
+    // Indices of the words ending here, including those via `fail`
+    outputs: Vec<usize>,
+}
+
+pub struct TokenScanner<V> {
+    nodes: Vec<Node>,
+    // Length and value of each word
+    words: Vec<(usize, V)>,
+}
+
+impl<V> TokenScanner<V> {
+    /// Build a scanner from (word, value) pairs.  Words must be
+    /// non-empty; if one appears twice the later value is used.
+    pub fn new<S: AsRef<str>, I: IntoIterator<Item = (S, V)>>(table: I) -> TokenScanner<V> {
+        let mut nodes = vec![Node::default()];
+        let mut words: Vec<(usize, V)> = Vec::new();
+        for (word, value) in table {
+            let word = word.as_ref().as_bytes();
+            assert!(!word.is_empty(), "Can't scan for an empty word");
+            let mut node = 0;
+            for &b in word {
+                node = match nodes[node].next.get(&b) {
+                    Some(&next) => next,
+                    None => {
+                        nodes.push(Node::default());
+                        let next = nodes.len() - 1;
+                        nodes[node].next.insert(b, next);
+                        next
+                    }
+                };
+            }
+            match nodes[node].outputs.first() {
+                Some(&i) => words[i].1 = value,
+                None => {
+                    nodes[node].outputs.push(words.len());
+                    words.push((word.len(), value));
+                }
+            }
+        }
+
+        // Failure links, breadth first so a node's suffixes are done
+        // before it.
+        let mut queue = nodes[0].next.values().copied().collect::<VecDeque<_>>();
+        while let Some(node) = queue.pop_front() {
+            let edges = nodes[node].next.iter().map(|(&b, &n)| (b, n)).collect::<Vec<_>>();
+            for (b, child) in edges {
+                let mut fail = nodes[node].fail;
+                let target = loop {
+                    if let Some(&n) = nodes[fail].next.get(&b) {
+                        break n;
+                    }
+                    if fail == 0 {
+                        break 0;
+                    }
+                    fail = nodes[fail].fail;
+                };
+                nodes[child].fail = target;
+                let inherited = nodes[target].outputs.clone();
+                nodes[child].outputs.extend(inherited);
+                queue.push_back(child);
+            }
+        }
+        TokenScanner { nodes, words }
+    }
+
+    /// Every match in the text, including overlapping ones, in the order
+    /// they end.
+    pub fn scan<'a>(&'a self, text: &'a str) -> impl Iterator<Item = Token<'a, V>> + 'a {
+        let mut node = 0;
+        text.bytes()
+            .enumerate()
+            .flat_map(move |(i, b)| {
+                node = loop {
+                    if let Some(&n) = self.nodes[node].next.get(&b) {
+                        break n;
+                    }
+                    if node == 0 {
+                        break 0;
+                    }
+                    node = self.nodes[node].fail;
+                };
+                self.nodes[node].outputs.iter().map(move |&w| {
+                    let (len, ref value) = self.words[w];
+                    Token { start: i + 1 - len, end: i + 1, value }
+                })
+            })
+    }
+
+    /// Every match, ordered by where it starts.
+    pub fn all<'a>(&'a self, text: &'a str) -> Vec<Token<'a, V>> {
+        let mut result = self.scan(text).collect::<Vec<_>>();
+        result.sort_by_key(|t| (t.start, t.end));
+        result
+    }
+
+    /// The match which starts first, taking the shortest if several do.
+    pub fn first<'a>(&'a self, text: &'a str) -> Option<Token<'a, V>> {
+        self.scan(text).min_by_key(|t| (t.start, t.end))
+    }
+
+    /// The match which starts last, taking the longest if several do.
+    pub fn last<'a>(&'a self, text: &'a str) -> Option<Token<'a, V>> {
+        self.scan(text).max_by_key(|t| (t.start, t.end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGITS: [(&str, u32); 9] = [
+        ("one", 1), ("two", 2), ("three", 3), ("four", 4), ("five", 5),
+        ("six", 6), ("seven", 7), ("eight", 8), ("nine", 9),
+    ];
+
+    fn spans<V: Copy>(tokens: &[Token<'_, V>]) -> Vec<(usize, usize, V)> {
+        tokens.iter().map(|t| (t.start, t.end, *t.value)).collect()
+    }
+
+    #[test]
+    fn overlapping() {
+        let scanner = TokenScanner::new(DIGITS);
+        assert_eq!(spans(&scanner.all("oneight")), vec![(0, 3, 1), (2, 7, 8)]);
+        assert_eq!(spans(&scanner.all("eightwo")), vec![(0, 5, 8), (4, 7, 2)]);
+        assert_eq!(spans(&scanner.all("xtwone3four")), vec![(1, 4, 2), (3, 6, 1), (7, 11, 4)]);
+        assert_eq!(scanner.first("eightwo").map(|t| *t.value), Some(8));
+        assert_eq!(scanner.last("eightwo").map(|t| *t.value), Some(2));
+        assert_eq!(scanner.first("zero"), None);
+    }
+
+    #[test]
+    fn prefixes() {
+        // Every word is a prefix of the next, so one node ends several.
+        let scanner = TokenScanner::new([("a", 1), ("ab", 2), ("abc", 3)]);
+        assert_eq!(spans(&scanner.all("abcab")),
+                   vec![(0, 1, 1), (0, 2, 2), (0, 3, 3), (3, 4, 1), (3, 5, 2)]);
+        // The shortest of several starting together comes first.
+        assert_eq!(scanner.first("abc").map(|t| *t.value), Some(1));
+        assert_eq!(scanner.last("abc").map(|t| *t.value), Some(3));
+
+        // A repeated word takes the later value.
+        let scanner = TokenScanner::new([("ab", 1), ("ab", 2)]);
+        assert_eq!(spans(&scanner.all("ab")), vec![(0, 2, 2)]);
+    }
+
+    #[test]
+    fn failure_links() {
+        // "he" is only found inside "she" as an output inherited through
+        // the failure link, and "hers" needs that link followed to carry
+        // on after "she".
+        let scanner = TokenScanner::new([("he", 1), ("she", 2), ("his", 3), ("hers", 4)]);
+        assert_eq!(spans(&scanner.scan("ushers").collect::<Vec<_>>()),
+                   vec![(1, 4, 2), (2, 4, 1), (2, 6, 4)]);
+        // A failed partial match mustn't hide one starting inside it.
+        let scanner = TokenScanner::new([("aab", 1), ("ab", 2)]);
+        assert_eq!(spans(&scanner.all("aaab")), vec![(1, 4, 1), (2, 4, 2)]);
+    }
+}
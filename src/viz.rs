@@ -0,0 +1,412 @@
+//! Terminal visualisation of grid-like puzzle state.
+//!
+//! A puzzle implements `Grid` to say what each cell looks like, and hands
+//! each state to a `Viewer`.  `Terminal` draws frames interactively with
+//! play/pause/step and speed controls; `TextDump` writes them to a file
+//! for non-interactive runs.
+
+use std::io::{self, Stdout, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use termion::AsyncReader;
+pub use termion::event::Key;
+use termion::input::{Keys, TermRead};
+use termion::raw::{IntoRawMode, RawTerminal};
+use termion::screen::{AlternateScreen, IntoAlternateScreen};
+use termion::{clear, color, cursor};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Colour {
+    Ansi(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Colour {
+    pub const RED: Colour = Colour::Ansi(1);
+    pub const GREEN: Colour = Colour::Ansi(2);
+    pub const YELLOW: Colour = Colour::Ansi(3);
+    pub const BLUE: Colour = Colour::Ansi(4);
+    pub const MAGENTA: Colour = Colour::Ansi(5);
+    pub const CYAN: Colour = Colour::Ansi(6);
+    pub const WHITE: Colour = Colour::Ansi(7);
+    pub const GREY: Colour = Colour::Ansi(8);
+}
+
+/// How one cell is drawn.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub glyph: char,
+    pub fg: Option<Colour>,
+    pub bg: Option<Colour>,
+}
+
+impl Cell {
+    pub fn fg(self, colour: Colour) -> Cell {
+        Cell { fg: Some(colour), ..self }
+    }
+
+    pub fn bg(self, colour: Colour) -> Cell {
+        Cell { bg: Some(colour), ..self }
+    }
+}
+
+impl From<char> for Cell {
+    fn from(glyph: char) -> Cell {
+        Cell { glyph, fg: None, bg: None }
+    }
+}
+
+/// Anything which can be drawn as a rectangle of cells.
+pub trait Grid {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    fn cell(&self, x: usize, y: usize) -> Cell;
+}
+
+/// A snapshot of a `Grid`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Frame {
+    pub fn capture<G: Grid + ?Sized>(grid: &G) -> Frame {
+        let width = grid.width();
+        let height = grid.height();
+        let mut cells = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                cells.push(grid.cell(x, y));
+            }
+        }
+        Frame { width, height, cells }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Cell {
+        self.cells[y * self.width + x]
+    }
+
+    /// Just the glyphs, one line per row.
+    pub fn to_text(&self) -> String {
+        let mut result = String::with_capacity((self.width + 1) * self.height);
+        for row in self.cells.chunks(self.width.max(1)) {
+            result.extend(row.iter().map(|c| c.glyph));
+            result.push('\n');
+        }
+        result
+    }
+
+    /// The glyphs with their colours as terminal escape codes.
+    pub fn to_ansi(&self) -> String {
+        let mut result = String::new();
+        for row in self.cells.chunks(self.width.max(1)) {
+            for &cell in row {
+                push_cell(&mut result, cell);
+            }
+            result.push('\n');
+        }
+        result
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Control {
+    Continue,
+    Quit,
+    /// A key the viewer doesn't use itself, for puzzles with controls of
+    /// their own.  Others can treat it like `Continue`.
+    Key(Key),
+}
+
+pub trait Viewer {
+    /// Display one frame, returning once the next one is wanted.
+    fn show(&mut self, frame: &Frame, status: &str) -> io::Result<Control>;
+
+    /// Whether someone is watching who might press keys, so it's worth
+    /// waiting on the last frame.
+    fn interactive(&self) -> bool {
+        false
+    }
+
+    fn show_grid(&mut self, grid: &dyn Grid, status: &str) -> io::Result<Control> {
+        self.show(&Frame::capture(grid), status)
+    }
+}
+
+fn push_colour(out: &mut String, colour: Colour, fg: bool) {
+    let s = match (colour, fg) {
+        (Colour::Ansi(n), true) => color::Fg(color::AnsiValue(n)).to_string(),
+        (Colour::Ansi(n), false) => color::Bg(color::AnsiValue(n)).to_string(),
+        (Colour::Rgb(r, g, b), true) => color::Fg(color::Rgb(r, g, b)).to_string(),
+        (Colour::Rgb(r, g, b), false) => color::Bg(color::Rgb(r, g, b)).to_string(),
+    };
+    out.push_str(&s);
+}
+
+fn push_cell(out: &mut String, cell: Cell) {
+    if let Some(fg) = cell.fg {
+        push_colour(out, fg, true);
+    }
+    if let Some(bg) = cell.bg {
+        push_colour(out, bg, false);
+    }
+    out.push(cell.glyph);
+    if cell.fg.is_some() {
+        out.push_str(&color::Fg(color::Reset).to_string());
+    }
+    if cell.bg.is_some() {
+        out.push_str(&color::Bg(color::Reset).to_string());
+    }
+}
+
+const HELP: &str = "space: play/pause  s: step  +/-: speed  q: quit";
+
+/// Interactive viewer in the terminal's alternate screen.  Each frame is
+/// compared with the last one drawn and only changed cells are sent.
+pub struct Terminal {
+    out: AlternateScreen<RawTerminal<Stdout>>,
+    keys: Keys<AsyncReader>,
+    last: Option<Frame>,
+    paused: bool,
+    delay: Duration,
+}
+
+impl Terminal {
+    pub fn new() -> io::Result<Terminal> {
+        let mut out = io::stdout().into_raw_mode()?.into_alternate_screen()?;
+        write!(out, "{}{}", cursor::Hide, clear::All)?;
+        Ok(Terminal {
+            out,
+            keys: termion::async_stdin().keys(),
+            last: None,
+            paused: false,
+            delay: Duration::from_millis(100),
+        })
+    }
+
+    fn draw(&mut self, frame: &Frame, status: &str) -> io::Result<()> {
+        let buf = redraw(self.last.as_ref(), frame, status, self.paused);
+        self.out.write_all(buf.as_bytes())?;
+        self.out.flush()?;
+        self.last = Some(frame.clone());
+        Ok(())
+    }
+}
+
+// The output to update the screen from `last` to `frame`, with the status
+// and help lines underneath.
+fn redraw(last: Option<&Frame>, frame: &Frame, status: &str, paused: bool) -> String {
+    let mut buf = String::new();
+    match last {
+        Some(last) if last.width == frame.width && last.height == frame.height => {
+            for y in 0..frame.height {
+                for x in 0..frame.width {
+                    let cell = frame.get(x, y);
+                    if cell != last.get(x, y) {
+                        buf.push_str(&cursor::Goto(x as u16 + 1, y as u16 + 1).to_string());
+                        push_cell(&mut buf, cell);
+                    }
+                }
+            }
+        }
+        _ => {
+            buf.push_str(clear::All.as_ref());
+            for y in 0..frame.height {
+                buf.push_str(&cursor::Goto(1, y as u16 + 1).to_string());
+                for x in 0..frame.width {
+                    push_cell(&mut buf, frame.get(x, y));
+                }
+            }
+        }
+    }
+    let status_row = frame.height as u16 + 1;
+    buf.push_str(&format!("{}{}{}{}",
+                          cursor::Goto(1, status_row), clear::CurrentLine, status,
+                          if paused { "  [paused]" } else { "" }));
+    buf.push_str(&format!("{}{}{}",
+                          cursor::Goto(1, status_row + 1), clear::CurrentLine, HELP));
+    buf
+}
+
+impl Viewer for Terminal {
+    fn show(&mut self, frame: &Frame, status: &str) -> io::Result<Control> {
+        self.draw(frame, status)?;
+        let start = Instant::now();
+        loop {
+            let mut redraw = false;
+            for key in self.keys.by_ref() {
+                match key? {
+                    Key::Char('q') | Key::Esc | Key::Ctrl('c') => {
+                        return Ok(Control::Quit);
+                    }
+                    Key::Char(' ') => {
+                        self.paused = !self.paused;
+                        redraw = true;
+                    }
+                    Key::Char('s') | Key::Char('.') => {
+                        self.paused = true;
+                        return Ok(Control::Continue);
+                    }
+                    Key::Char('+') => {
+                        self.delay = (self.delay / 2).max(Duration::from_millis(1));
+                    }
+                    Key::Char('-') => {
+                        self.delay = (self.delay * 2).min(Duration::from_secs(5));
+                    }
+                    key => {
+                        return Ok(Control::Key(key));
+                    }
+                }
+            }
+            if redraw {
+                self.draw(frame, status)?;
+            }
+            if !self.paused && start.elapsed() >= self.delay {
+                return Ok(Control::Continue);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn interactive(&self) -> bool {
+        true
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = write!(self.out, "{}", cursor::Show);
+        let _ = self.out.flush();
+    }
+}
+
+/// Writes each frame as plain text, separated by a header line.
+pub struct TextDump<W: Write> {
+    out: W,
+    frames: usize,
+}
+
+impl<W: Write> TextDump<W> {
+    pub fn new(out: W) -> TextDump<W> {
+        TextDump { out, frames: 0 }
+    }
+}
+
+impl<W: Write> Viewer for TextDump<W> {
+    fn show(&mut self, frame: &Frame, status: &str) -> io::Result<Control> {
+        writeln!(self.out, "--- frame {}: {}", self.frames, status)?;
+        self.out.write_all(frame.to_text().as_bytes())?;
+        self.frames += 1;
+        Ok(Control::Continue)
+    }
+}
+
+/// The viewer asked for on the command line: `--viz` for the terminal or
+/// `--dump <file>` for a text dump, if either.
+pub fn viewer_from_args() -> io::Result<Option<Box<dyn Viewer>>> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--viz" => {
+                return Ok(Some(Box::new(Terminal::new()?)));
+            }
+            "--dump" => {
+                let filename = args.next().expect("--dump needs a filename");
+                let file = io::BufWriter::new(std::fs::File::create(filename)?);
+                return Ok(Some(Box::new(TextDump::new(file))));
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Letters(&'static [&'static str]);
+
+    impl Grid for Letters {
+        fn width(&self) -> usize {
+            self.0[0].len()
+        }
+        fn height(&self) -> usize {
+            self.0.len()
+        }
+        fn cell(&self, x: usize, y: usize) -> Cell {
+            let glyph = self.0[y].as_bytes()[x] as char;
+            match glyph {
+                'R' => Cell::from(glyph).fg(Colour::RED),
+                'B' => Cell::from(glyph).bg(Colour::Rgb(0, 0, 255)),
+                _ => glyph.into(),
+            }
+        }
+    }
+
+    #[test]
+    fn text() {
+        let frame = Frame::capture(&Letters(&["ab", "RB"]));
+        assert_eq!((frame.width(), frame.height()), (2, 2));
+        assert_eq!(frame.to_text(), "ab\nRB\n");
+        assert_eq!(frame.get(0, 1), Cell { glyph: 'R', fg: Some(Colour::RED), bg: None });
+    }
+
+    #[test]
+    fn ansi() {
+        let frame = Frame::capture(&Letters(&["aR", "B."]));
+        let expected = format!("a{}R{}\n{}B{}.\n",
+                               color::Fg(color::AnsiValue(1)), color::Fg(color::Reset),
+                               color::Bg(color::Rgb(0, 0, 255)), color::Bg(color::Reset));
+        assert_eq!(frame.to_ansi(), expected);
+    }
+
+    #[test]
+    fn text_dump() {
+        let mut out = Vec::new();
+        let mut dump = TextDump::new(&mut out);
+        let frame = Frame::capture(&Letters(&["aR"]));
+        assert_eq!(dump.show(&frame, "first").unwrap(), Control::Continue);
+        assert_eq!(dump.show_grid(&Letters(&["Ba"]), "second").unwrap(), Control::Continue);
+        assert!(!dump.interactive());
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "--- frame 0: first\naR\n--- frame 1: second\nBa\n");
+    }
+
+    #[test]
+    fn redraw_changes() {
+        let first = Frame::capture(&Letters(&["abc", "def"]));
+        let second = Frame::capture(&Letters(&["abc", "dRf"]));
+        let status = |row| format!("{}{}status{}{}{}",
+                                   cursor::Goto(1, row), clear::CurrentLine,
+                                   cursor::Goto(1, row + 1), clear::CurrentLine, HELP);
+
+        // The first frame is drawn in full.
+        let full = redraw(None, &first, "status", false);
+        assert_eq!(full, format!("{}{}abc{}def{}", clear::All,
+                                 cursor::Goto(1, 1), cursor::Goto(1, 2), status(3)));
+
+        // After that only the changed cell is sent.
+        let update = redraw(Some(&first), &second, "status", false);
+        assert_eq!(update, format!("{}{}R{}{}", cursor::Goto(2, 2),
+                                   color::Fg(color::AnsiValue(1)), color::Fg(color::Reset),
+                                   status(3)));
+        assert_eq!(redraw(Some(&second), &second, "status", false), status(3));
+
+        // A change of size means starting again.
+        let wider = Frame::capture(&Letters(&["abcd", "defg"]));
+        assert!(redraw(Some(&first), &wider, "status", false).starts_with(&clear::All.to_string()));
+        assert!(redraw(Some(&first), &first, "status", true).contains("status  [paused]"));
+    }
+}